@@ -1,3 +0,0 @@
-#!/bin/sh
-
-python3 -m http.server 1921 -d $(dirname "$0")
\ No newline at end of file
@@ -0,0 +1,116 @@
+// Module for checksums provided by repository metadata (APT Packages, YUM primary.xml, etc.)
+
+use std::{io::Read, path::Path};
+
+use anyhow::Result;
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha512};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Checksum {
+    /// Lowercase hex digest
+    Sha256(String),
+    Sha512(String),
+    /// Only used where no better one is provided (like CRAN PACKAGES)
+    Md5(String),
+}
+
+fn hash_file<D: Digest>(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = D::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+impl Checksum {
+    /// Build from algorithm name used in metadata (like "sha256"). None if not supported.
+    pub fn new(algorithm: &str, digest: &str) -> Option<Self> {
+        let digest = digest.trim().to_lowercase();
+        match algorithm.to_lowercase().as_str() {
+            "sha256" => Some(Self::Sha256(digest)),
+            "sha512" => Some(Self::Sha512(digest)),
+            "md5" | "md5sum" => Some(Self::Md5(digest)),
+            _ => None,
+        }
+    }
+
+    pub fn verify(&self, path: &Path) -> Result<bool> {
+        Ok(match self {
+            Self::Sha256(digest) => hash_file::<Sha256>(path)? == *digest,
+            Self::Sha512(digest) => hash_file::<Sha512>(path)? == *digest,
+            Self::Md5(digest) => hash_file::<Md5>(path)? == *digest,
+        })
+    }
+}
+
+impl std::fmt::Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sha256(digest) => write!(f, "sha256:{digest}"),
+            Self::Sha512(digest) => write!(f, "sha512:{digest}"),
+            Self::Md5(digest) => write!(f, "md5:{digest}"),
+        }
+    }
+}
+
+/// Check size and checksum of a file, if they are known.
+pub fn verify_file(path: &Path, size: Option<u64>, checksum: Option<&Checksum>) -> Result<()> {
+    if let Some(size) = size {
+        let local_size = path.metadata()?.len();
+        if local_size != size {
+            return Err(anyhow::anyhow!(
+                "Size mismatch of {:?}: expected {}, got {}",
+                path,
+                size,
+                local_size
+            ));
+        }
+    }
+    if let Some(checksum) = checksum {
+        if !checksum.verify(path)? {
+            return Err(anyhow::anyhow!(
+                "Checksum mismatch of {:?}: expected {}",
+                path,
+                checksum
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksum() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/checksum/hello.txt");
+        let data = std::fs::read(&path).unwrap();
+        let digest = format!("{:x}", Sha256::digest(&data));
+        assert_eq!(
+            digest,
+            "aaa6f42566aff305128b040931addd235c250d86bf5f89495099d5d8b7d74998"
+        );
+        let checksum = Checksum::new("SHA256", &digest.to_uppercase()).unwrap();
+        assert!(checksum.verify(&path).unwrap());
+        assert!(verify_file(&path, Some(data.len() as u64), Some(&checksum)).is_ok());
+        assert!(verify_file(&path, Some(data.len() as u64 + 1), None).is_err());
+        let wrong = Checksum::new("sha256", &"0".repeat(64)).unwrap();
+        assert!(!wrong.verify(&path).unwrap());
+        assert!(verify_file(&path, None, Some(&wrong)).is_err());
+        let md5 = Checksum::new("MD5sum", &format!("{:x}", Md5::digest(&data))).unwrap();
+        assert!(md5.verify(&path).unwrap());
+        assert_eq!(Checksum::new("crc32", "abc"), None);
+    }
+}
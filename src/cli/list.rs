@@ -4,7 +4,13 @@ use crate::{build_client, parser::ListResult, regex_process::ExclusionManager, L
 
 // TODO: clean code
 pub fn list(args: &ListArgs, bind_address: Option<String>) -> ! {
-    let parser = args.parser.build();
+    let parser = match args.parser.build(args.parser_config.as_deref()) {
+        Ok(parser) => parser,
+        Err(e) => {
+            tracing::error!("Failed to build parser: {:?}", e);
+            std::process::exit(1);
+        }
+    };
     let client = build_client!(reqwest::blocking::Client, args, parser, bind_address);
     let exclusion_manager = ExclusionManager::new(&args.exclude, &args.include);
     // get relative
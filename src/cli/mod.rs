@@ -1,4 +1,6 @@
 mod list;
+mod parse;
 mod sync;
 pub use list::list;
+pub use parse::parse;
 pub use sync::sync;
@@ -0,0 +1,74 @@
+use std::io::Read;
+
+use crate::{
+    listing::{FileSize, ListItem},
+    parser::ListResult,
+    ParseArgs,
+};
+
+fn item_to_json(item: &ListItem) -> serde_json::Value {
+    serde_json::json!({
+        "url": item.url.as_str(),
+        "name": item.name,
+        "type": format!("{:?}", item.type_),
+        "size": item.size.map(|s| match s {
+            FileSize::Precise(size) => serde_json::json!(size),
+            _ => serde_json::json!(s.to_string()),
+        }),
+        "mtime": item.mtime.format("%Y-%m-%d %H:%M:%S").to_string(),
+        "timezone": item.timezone.map(|tz| tz.to_string()),
+        "etag": item.etag,
+    })
+}
+
+pub fn parse(args: &ParseArgs) -> ! {
+    let parser = match args.parser.build(args.parser_config.as_deref()) {
+        Ok(parser) => parser,
+        Err(e) => {
+            tracing::error!("Failed to build parser: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    let body = if args.file.as_os_str() == "-" {
+        let mut body = String::new();
+        std::io::stdin().read_to_string(&mut body).map(|_| body)
+    } else {
+        std::fs::read_to_string(&args.file)
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Failed to read {:?}: {:?}", args.file, e);
+            std::process::exit(1);
+        }
+    };
+    // Listing URL is a directory
+    let mut base_url = args.base_url.clone();
+    if !base_url.path().ends_with('/') {
+        base_url.set_path(&format!("{}/", base_url.path()));
+    }
+    let list = match parser.parse_body(&body, &base_url) {
+        Ok(list) => list,
+        Err(e) => {
+            tracing::error!("Failed to parse: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match list {
+        ListResult::Redirect(url) => {
+            println!("Redirect to {url}");
+        }
+        ListResult::List(list) => {
+            for item in list {
+                if args.json {
+                    println!("{}", item_to_json(&item));
+                } else {
+                    println!("{item}");
+                }
+            }
+        }
+    }
+
+    std::process::exit(0);
+}
@@ -1,7 +1,8 @@
 use std::{
-    collections::HashSet,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
     fs::File,
-    io::Write,
+    io::{Read, Write},
     os::unix::fs::symlink,
     path::{Path, PathBuf},
     sync::{
@@ -11,7 +12,7 @@ use std::{
 };
 
 use anyhow::Result;
-use chrono::{FixedOffset, NaiveDateTime};
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
 use crossbeam_deque::{Injector, Worker};
 use futures_util::StreamExt;
 use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
@@ -20,20 +21,29 @@ use url::Url;
 
 use crate::{
     build_client,
-    compare::{should_download_by_head, should_download_by_list},
-    extensions::{extension_handler, ExtensionPackage},
-    listing::{self, ListItem},
-    parser::ListResult,
+    checksum::verify_file,
+    compare::{should_download_by_head, should_download_by_list, should_download_by_state},
+    extensions::{
+        extension_handler, nix, ExtensionPackage, ExtensionType, RepoExtension, UPSTREAM_DIR,
+    },
+    listing::{self, FileSize, ListItem},
+    parser::{ftp, ListResult},
     regex_process::{self, ExclusionManager},
+    signature::Keyring,
+    staging::Staging,
+    state::{StateEntry, SyncState},
     term::AlternativeTerm,
-    utils::{self, again, again_async, get_async, head, is_symlink, naive_to_utc},
+    utils::{
+        self, again, content_range_start, get_async, get_async_range, head, is_symlink,
+        naive_to_utc,
+    },
     SyncArgs,
 };
 
 #[derive(Debug, Clone)]
 enum TaskType {
     Listing,
-    Download(ListItem),
+    Download(Box<ListItem>),
 }
 
 #[derive(Debug, Clone)]
@@ -48,24 +58,30 @@ fn worker_add_task(worker: &Worker<Task>, wake: &AtomicUsize, task: Task) {
     wake.fetch_add(1, Ordering::SeqCst);
 }
 
-fn extension_push_task(worker: &Worker<Task>, wake: &AtomicUsize, package: &ExtensionPackage) {
-    worker_add_task(
-        worker,
-        wake,
-        Task {
-            task: TaskType::Download(ListItem {
-                url: package.url.clone(),
-                name: package.filename.clone(),
-                type_: listing::FileType::File,
-                // size and mtime would be ignored as skip_check is set
-                size: None,
-                mtime: NaiveDateTime::default(),
-                skip_check: true,
-            }),
-            relative: package.relative.clone(),
+fn package_task(package: &ExtensionPackage) -> Task {
+    Task {
+        task: TaskType::Download(Box::new(ListItem {
             url: package.url.clone(),
-        },
-    );
+            name: package.filename.clone(),
+            type_: listing::FileType::File,
+            // mtime would be ignored as skip_check is set,
+            // while size and checksum are used to verify the package
+            size: package.size.map(FileSize::Precise),
+            mtime: NaiveDateTime::default(),
+            timezone: None,
+            etag: None,
+            checksum: package.checksum.clone(),
+            skip_check: true,
+            optional: package.optional,
+            attributes: vec![],
+        })),
+        relative: package.relative.clone(),
+        url: package.url.clone(),
+    }
+}
+
+fn extension_push_task(worker: &Worker<Task>, wake: &AtomicUsize, package: &ExtensionPackage) {
+    worker_add_task(worker, wake, package_task(package));
 }
 
 fn determinate_timezone(
@@ -86,8 +102,13 @@ fn determinate_timezone(
                 },
                 None => {
                     // eek, try getting first file in root index
-                    let list =
-                        again(|| parser.get_list(client, &args.upstream), args.retry).unwrap();
+                    let list = match again(|| parser.get_list(client, &args.upstream), args.retry) {
+                        Ok(list) => list,
+                        Err(e) => {
+                            warn!("Failed to list root index ({e}), disabling timezone guessing");
+                            return None;
+                        }
+                    };
                     match list {
                         ListResult::List(list) => {
                             match list.iter().find(|x| x.type_ == listing::FileType::File) {
@@ -95,6 +116,13 @@ fn determinate_timezone(
                                     warn!("No files in root index, disabling timezone guessing");
                                     None
                                 }
+                                Some(x) if x.timezone.is_some() => {
+                                    info!(
+                                        "Using timezone from listing: {:?}, no need to guess",
+                                        x.timezone
+                                    );
+                                    return x.timezone;
+                                }
                                 Some(x) => Some(x.url.clone()),
                             }
                         }
@@ -128,6 +156,108 @@ fn determinate_timezone(
     }
 }
 
+fn new_progress_bar(mprogress: &MultiProgress, total_size: u64, url: &Url) -> ProgressBar {
+    let pb = mprogress.add(ProgressBar::new(total_size));
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg}\n[{elapsed_precise}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    pb.set_message(format!("Downloading {}", url));
+    pb
+}
+
+/// Paths of partial download and its validator (ETag or Last-Modified of the response it comes from).
+fn tmp_paths(cwd: &Path, name: &str) -> (PathBuf, PathBuf) {
+    (
+        cwd.join(format!(".tmp.{}", name)),
+        cwd.join(format!(".tmp.{}.validator", name)),
+    )
+}
+
+/// Validator which could be used in If-Range. Weak ETags are not allowed there.
+fn get_validator(resp: &reqwest::Response) -> Option<String> {
+    let headers = resp.headers();
+    headers
+        .get(reqwest::header::ETAG)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.starts_with("W/"))
+        .or_else(|| {
+            headers
+                .get(reqwest::header::LAST_MODIFIED)
+                .and_then(|v| v.to_str().ok())
+        })
+        .map(|v| v.to_string())
+}
+
+/// Verify downloaded package with size and checksum from repository metadata.
+/// The broken file is removed so that it would be downloaded again from start.
+fn verify_package(item: &ListItem, tmp_path: &Path) -> Result<()> {
+    if !item.skip_check {
+        return Ok(());
+    }
+    let size = match item.size {
+        Some(FileSize::Precise(size)) => Some(size),
+        _ => None,
+    };
+    verify_file(tmp_path, size, item.checksum.as_ref()).inspect_err(|_| {
+        let (tmp_path, validator_path) = tmp_paths(tmp_path.parent().unwrap(), &item.name);
+        let _ = std::fs::remove_file(tmp_path);
+        let _ = std::fs::remove_file(validator_path);
+    })
+}
+
+/// Send GET, resuming partial download in tmp_path if possible. Returns response and offset to write from.
+async fn get_resumable(
+    client: &reqwest::Client,
+    item: &ListItem,
+    tmp_path: &Path,
+    validator_path: &Path,
+) -> Result<(reqwest::Response, u64)> {
+    let offset = tmp_path.metadata().map(|m| m.len()).unwrap_or(0);
+    let validator = std::fs::read_to_string(validator_path).ok();
+    let validator = match validator {
+        Some(validator) if offset > 0 => validator,
+        _ => return Ok((get_async(client, item.url.clone()).await?, 0)),
+    };
+    info!("Resuming {} from {} bytes", item.url, offset);
+    let resp = get_async_range(client, item.url.clone(), offset, validator.trim()).await?;
+    match resp.status() {
+        reqwest::StatusCode::PARTIAL_CONTENT => {
+            let start = resp
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .and_then(content_range_start);
+            if start != Some(offset) {
+                // Start over in next try
+                let _ = std::fs::remove_file(tmp_path);
+                return Err(anyhow::anyhow!(
+                    "Unexpected Content-Range {:?} when resuming {}",
+                    start,
+                    item.url
+                ));
+            }
+            Ok((resp, offset))
+        }
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE => {
+            info!(
+                "Range not satisfiable for {}, downloading from start",
+                item.url
+            );
+            Ok((get_async(client, item.url.clone()).await?, 0))
+        }
+        _ => {
+            info!(
+                "{} changed or Range is not supported, downloading from start",
+                item.url
+            );
+            Ok((resp, 0))
+        }
+    }
+}
+
 async fn download_file(
     client: &reqwest::Client,
     item: &ListItem,
@@ -139,28 +269,17 @@ async fn download_file(
 ) -> Result<()> {
     // Here we use async to allow streaming and progress bar
     // Ref: https://gist.github.com/giuliano-oliveira/4d11d6b3bb003dba3a1b53f43d81b30d
-    let resp = match again_async(|| get_async(client, item.url.clone()), args.retry).await {
-        Ok(resp) => resp,
-        Err(e) => {
-            error!("Failed to GET {}: {:?}", item.url, e);
-            return Err(e);
-        }
-    };
-    let total_size = resp.content_length().unwrap();
-    let pb = mprogress.add(ProgressBar::new(total_size));
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{msg}\n[{elapsed_precise}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
-    pb.set_message(format!("Downloading {}", item.url));
+    let (tmp_path, validator_path) = tmp_paths(cwd, &item.name);
+    let (resp, offset) = get_resumable(client, item, &tmp_path, &validator_path).await?;
+    let total_size = offset + resp.content_length().unwrap_or_default();
+    let pb = new_progress_bar(mprogress, total_size, &item.url);
+    pb.set_position(offset);
 
     let mtime = match utils::get_async_response_mtime(&resp) {
         Ok(mtime) => mtime,
         Err(e) => {
             if args.allow_mtime_from_parser {
-                naive_to_utc(&item.mtime, timezone)
+                naive_to_utc(&item.mtime, item.timezone.or(timezone))
             } else {
                 error!("Failed to get mtime of {}: {:?}", item.url, e);
                 return Err(e);
@@ -168,14 +287,24 @@ async fn download_file(
         }
     };
 
-    let tmp_path = cwd.join(format!(".tmp.{}", item.name));
     {
-        let mut dest_file = File::create(&tmp_path).unwrap();
+        let mut dest_file = if offset > 0 {
+            std::fs::OpenOptions::new().append(true).open(&tmp_path)?
+        } else {
+            // Remember where the partial download comes from, for resuming it later
+            match get_validator(&resp) {
+                Some(validator) => std::fs::write(&validator_path, validator)?,
+                None => {
+                    let _ = std::fs::remove_file(&validator_path);
+                }
+            }
+            File::create(&tmp_path)?
+        };
         let mut stream = resp.bytes_stream();
 
         while let Some(item) = stream.next().await {
-            let chunk = item.unwrap();
-            dest_file.write_all(&chunk).unwrap();
+            let chunk = item?;
+            dest_file.write_all(&chunk)?;
             let new = std::cmp::min(pb.position() + (chunk.len() as u64), total_size);
             pb.set_position(new);
         }
@@ -183,14 +312,172 @@ async fn download_file(
             &dest_file,
             None,
             Some(filetime::FileTime::from_system_time(mtime.into())),
-        )
-        .unwrap();
+        )?;
     }
+    verify_package(item, &tmp_path)?;
     // move tmp file to expected path
-    std::fs::rename(&tmp_path, path).unwrap();
+    std::fs::rename(&tmp_path, path)?;
+    let _ = std::fs::remove_file(&validator_path);
     Ok(())
 }
 
+fn download_ftp_file(
+    item: &ListItem,
+    path: &Path,
+    args: &SyncArgs,
+    mprogress: &MultiProgress,
+    timezone: Option<FixedOffset>,
+    cwd: &Path,
+) -> Result<()> {
+    let mut stream = ftp::connect(&item.url)?;
+    let remote_path = ftp::decode_path(item.url.path());
+    let mtime = match stream.mdtm(&remote_path) {
+        // MDTM is always in UTC
+        Ok(mtime) => DateTime::<Utc>::from_naive_utc_and_offset(mtime, Utc),
+        Err(e) => {
+            if args.allow_mtime_from_parser {
+                naive_to_utc(&item.mtime, item.timezone.or(timezone))
+            } else {
+                error!("Failed to get mtime of {}: {:?}", item.url, e);
+                return Err(e.into());
+            }
+        }
+    };
+    let total_size = match item.size {
+        Some(FileSize::Precise(size)) => size,
+        _ => stream.size(&remote_path)? as u64,
+    };
+    let pb = new_progress_bar(mprogress, total_size, &item.url);
+
+    let (tmp_path, _) = tmp_paths(cwd, &item.name);
+    {
+        let mut dest_file = File::create(&tmp_path)?;
+        let mut reader = stream.retr_as_stream(&remote_path)?;
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            dest_file.write_all(&buf[..n])?;
+            let new = std::cmp::min(pb.position() + (n as u64), total_size);
+            pb.set_position(new);
+        }
+        reader.finish()?;
+        filetime::set_file_handle_times(
+            &dest_file,
+            None,
+            Some(filetime::FileTime::from_system_time(mtime.into())),
+        )?;
+    }
+    let _ = stream.quit();
+    verify_package(item, &tmp_path)?;
+    // move tmp file to expected path
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// Packages referred by metadata parsed by extensions, for --delete-unreferenced.
+#[derive(Default)]
+struct References {
+    /// Expected paths of referred packages
+    packages: Mutex<HashSet<PathBuf>>,
+    /// Package roots of parsed metadata (see `RepoExtension::package_root`),
+    /// and whether all metadata for them is parsed successfully
+    dirs: Mutex<HashMap<PathBuf, bool>>,
+}
+
+impl References {
+    /// Record whether metadata at `path` (synced into `cwd`) is parsed, for its package roots.
+    fn record_parsed(
+        &self,
+        extensions: &[Box<dyn RepoExtension>],
+        path: &Path,
+        cwd: &Path,
+        parsed: bool,
+    ) {
+        let mut dirs = self.dirs.lock().unwrap();
+        for extension in extensions.iter().filter(|e| e.matches(path)) {
+            *dirs
+                .entry(extension.package_root(cwd).to_path_buf())
+                .or_insert(true) &= parsed;
+        }
+    }
+
+    /// Drop unreferenced packages from remote list, so that they are deleted later, and return them.
+    /// Only packages under directories whose metadata are all parsed are considered,
+    /// and directories and hidden files (like partial downloads) are kept.
+    fn drop_unreferenced(
+        &self,
+        extensions: &[Box<dyn RepoExtension>],
+        download_dir: &Path,
+        remote_list: &mut HashSet<PathBuf>,
+    ) -> HashSet<PathBuf> {
+        let packages = self.packages.lock().unwrap();
+        let dirs = self.dirs.lock().unwrap();
+        let mut unreferenced_list = HashSet::new();
+        remote_list.retain(|path| {
+            let Ok(relative) = path.strip_prefix(download_dir) else {
+                return true;
+            };
+            if path.is_dir()
+                || path
+                    .file_name()
+                    .is_some_and(|f| f.to_string_lossy().starts_with('.'))
+            {
+                return true;
+            }
+            let relative = relative.to_string_lossy();
+            let unreferenced = !packages.contains(path)
+                && extensions.iter().any(|e| e.is_package(&relative))
+                // Only the nearest package root decides, as nested ones might fail on their own
+                && path.ancestors().skip(1).find_map(|dir| dirs.get(dir)) == Some(&true);
+            if unreferenced {
+                info!("{:?} is not referred by metadata", path);
+                unreferenced_list.insert(path.clone());
+            }
+            !unreferenced
+        });
+        unreferenced_list
+    }
+}
+
+/// Files generated for the mirror (like rewritten Helm index, or local index of parser),
+/// written after files are synced, so that they are never served before what they refer to.
+#[derive(Default)]
+struct LocalFiles(Mutex<Vec<(PathBuf, String)>>);
+
+impl LocalFiles {
+    fn add(&self, path: PathBuf, content: String) {
+        self.0.lock().unwrap().push((path, content));
+    }
+
+    /// Write files (atomically) if changed. Returns whether all are written.
+    fn write(&self, dry_run: bool) -> bool {
+        let mut ok = true;
+        for (path, content) in self.0.lock().unwrap().iter() {
+            if std::fs::read_to_string(path).is_ok_and(|c| c == *content) {
+                continue;
+            }
+            if dry_run {
+                info!("Dry run, not writing {:?}", path);
+                continue;
+            }
+            info!("Writing {:?}", path);
+            let dir = path.parent().unwrap();
+            let (tmp_path, _) = tmp_paths(dir, &path.file_name().unwrap().to_string_lossy());
+            if let Err(e) = std::fs::create_dir_all(dir)
+                .and_then(|_| std::fs::write(&tmp_path, content))
+                .and_then(|_| std::fs::rename(&tmp_path, path))
+            {
+                error!("Failed to write {:?}: {:?}", path, e);
+                ok = false;
+            }
+        }
+        ok
+    }
+}
+
 struct ThreadsContext<'a> {
     bind_address: Option<String>,
     download_dir: &'a Path,
@@ -199,6 +486,16 @@ struct ThreadsContext<'a> {
     stat_size: &'a AtomicU64,
     failure_listing: &'a AtomicBool,
     failure_downloading: &'a AtomicBool,
+    state: Option<&'a SyncState>,
+    staging: Option<&'a Staging>,
+    extensions: &'a [Box<dyn RepoExtension>],
+    references: Option<&'a References>,
+    /// Files to start with, instead of listing upstream
+    seeds: &'a [ExtensionPackage],
+    /// Local paths of packages queued by extensions (or as seeds), so that each is queued once,
+    /// as Nix narinfo could refer to each other
+    queued: &'a Mutex<HashSet<PathBuf>>,
+    local_files: &'a LocalFiles,
 }
 
 struct TaskContext<'a> {
@@ -220,6 +517,39 @@ struct AsyncDownloadContext<'a> {
     runtime: &'a tokio::runtime::Runtime,
 }
 
+/// Add index page of parser (if any) for local directory, listing items not excluded,
+/// to be written after sync with --local-index.
+fn write_local_index(
+    args: &SyncArgs,
+    parser: &dyn crate::parser::Parser,
+    thr_context: &ThreadsContext,
+    task_context: &TaskContext,
+    items: &[ListItem],
+) {
+    if !args.local_index {
+        return;
+    }
+    let items: Vec<ListItem> = items
+        .iter()
+        .filter(|item| {
+            let relative = PathBuf::from(task_context.relative).join(&item.name);
+            task_context
+                .exclusion_manager
+                .match_str(&relative.to_string_lossy())
+                != regex_process::Comparison::Stop
+                && (item.type_ == listing::FileType::Directory
+                    || task_context.exclusion_result != regex_process::Comparison::ListOnly)
+        })
+        .cloned()
+        .collect();
+    let Some(page) = parser.local_index(&items) else {
+        return;
+    };
+    let path = task_context.cwd.join("index.html");
+    thr_context.remote_list.lock().unwrap().insert(path.clone());
+    thr_context.local_files.add(path, page);
+}
+
 fn list_handler(
     args: &SyncArgs,
     parser: &dyn crate::parser::Parser,
@@ -253,6 +583,9 @@ fn list_handler(
             return;
         }
     };
+    if let ListResult::List(items) = &items {
+        write_local_index(args, parser, thr_context, task_context, items);
+    }
     match items {
         ListResult::List(items) => {
             for item in items {
@@ -277,7 +610,7 @@ fn list_handler(
                         task_context.worker,
                         task_context.wake,
                         Task {
-                            task: TaskType::Download(item.clone()),
+                            task: TaskType::Download(Box::new(item.clone())),
                             relative: task.relative.clone(),
                             url: item.url,
                         },
@@ -322,6 +655,151 @@ fn list_handler(
     }
 }
 
+/// Download item to path, by FTP or HTTP(S).
+fn download(
+    item: &ListItem,
+    path: &Path,
+    args: &SyncArgs,
+    task_context: &TaskContext,
+    async_context: &AsyncDownloadContext,
+) -> Result<()> {
+    // tmp files are put beside the target, which might be in staging dir
+    let cwd = path.parent().unwrap();
+    if item.url.scheme() == "ftp" {
+        again(
+            || {
+                download_ftp_file(
+                    item,
+                    path,
+                    args,
+                    async_context.mprogress,
+                    task_context.timezone,
+                    cwd,
+                )
+            },
+            args.retry,
+        )
+        .inspect_err(|e| error!("Failed to RETR {}: {:?}", item.url, e))
+    } else {
+        // Partial download is kept and resumed in next try
+        again(
+            || {
+                async_context.runtime.block_on(download_file(
+                    async_context.async_client,
+                    item,
+                    path,
+                    args,
+                    async_context.mprogress,
+                    task_context.timezone,
+                    cwd,
+                ))
+            },
+            args.retry,
+        )
+        .inspect_err(|e| error!("Failed to GET {}: {:?}", item.url, e))
+    }
+}
+
+fn is_not_found(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<reqwest::Error>()
+        .and_then(|e| e.status())
+        .is_some_and(|s| s == reqwest::StatusCode::NOT_FOUND)
+}
+
+fn record_state(state: Option<&SyncState>, relative: &str, item: &ListItem, path: &Path) {
+    if let Some(state) = state {
+        if let Some(entry) = StateEntry::new(item, path) {
+            state.record(relative, entry);
+        }
+    }
+}
+
+/// Download item to expected path, or to staging dir if it is metadata.
+/// Returns where the file is put.
+fn download_and_record(
+    item: &ListItem,
+    expected_path: &Path,
+    relative_filepath: &str,
+    args: &SyncArgs,
+    thr_context: &ThreadsContext,
+    task_context: &TaskContext,
+    async_context: &AsyncDownloadContext,
+) -> PathBuf {
+    let staging = thr_context.staging.filter(|s| {
+        s.is_metadata(relative_filepath)
+            || thr_context
+                .extensions
+                .iter()
+                .any(|e| e.is_metadata(relative_filepath))
+    });
+    let path = match staging {
+        Some(staging) => staging.path(relative_filepath),
+        None => expected_path.to_path_buf(),
+    };
+    if let Err(e) = std::fs::create_dir_all(path.parent().unwrap()) {
+        error!("Failed to create directory for {:?}: {:?}", path, e);
+        thr_context
+            .failure_downloading
+            .store(true, Ordering::SeqCst);
+        return expected_path.to_path_buf();
+    }
+    if let Err(e) = download(item, &path, args, task_context, async_context) {
+        if item.optional && is_not_found(&e) {
+            info!("Optional {} not found, skipping", item.url);
+            // Not a remote file, so that the local one (if any) is deleted
+            thr_context
+                .remote_list
+                .lock()
+                .unwrap()
+                .remove(expected_path);
+            return expected_path.to_path_buf();
+        }
+        thr_context
+            .failure_downloading
+            .store(true, Ordering::SeqCst);
+        // Keep partial download to resume in next run
+        let (tmp_path, validator_path) = tmp_paths(path.parent().unwrap(), &item.name);
+        let mut remote_list = thr_context.remote_list.lock().unwrap();
+        remote_list.insert(tmp_path);
+        remote_list.insert(validator_path);
+        return expected_path.to_path_buf();
+    }
+    if let Some(staging) = staging {
+        staging.stage(relative_filepath);
+    }
+    record_state(thr_context.state, relative_filepath, item, &path);
+    path
+}
+
+/// Path (and relative one) to sync file rewritten for the mirror to. It is kept as is from upstream
+/// in UPSTREAM_DIR, and the local one is written after sync.
+fn upstream_location(download_dir: &Path, relative: &str) -> (PathBuf, Cow<'static, str>) {
+    let relative = format!("{UPSTREAM_DIR}/{relative}");
+    (download_dir.join(&relative), Cow::Owned(relative))
+}
+
+/// Rewrite upstream file at `path` (if synced) for the mirror, to be written to `local_file` after sync.
+fn rewrite_local_file(
+    extension: &dyn RepoExtension,
+    path: &Path,
+    local_file: PathBuf,
+    url: &Url,
+    thr_context: &ThreadsContext,
+) {
+    if !path.exists() {
+        return;
+    }
+    match extension.rewrite(path, url) {
+        Ok(content) => thr_context.local_files.add(local_file, content),
+        Err(e) => {
+            error!("Failed to rewrite {:?}: {:?}", path, e);
+            thr_context
+                .failure_downloading
+                .store(true, Ordering::SeqCst);
+        }
+    }
+}
+
 fn download_handler(
     item: &ListItem,
     args: &SyncArgs,
@@ -331,10 +809,6 @@ fn download_handler(
 ) {
     let task = task_context.task;
     let cwd = task_context.cwd;
-    // create path in case for first sync
-    if !args.dry_run {
-        std::fs::create_dir_all(cwd).unwrap();
-    }
     // Absolute filesystem path of expected file
     let expected_path = cwd.join(&item.name);
     // Here relative filepath is only used to check exclusion
@@ -355,30 +829,52 @@ fn download_handler(
         return;
     }
 
+    let rewriter = thr_context
+        .extensions
+        .iter()
+        .find(|e| e.is_rewritten(&relative_filepath));
+    let local_file = expected_path.clone();
+    let (expected_path, relative_filepath) = if rewriter.is_some() {
+        upstream_location(thr_context.download_dir, &relative_filepath)
+    } else {
+        (expected_path, relative_filepath)
+    };
+
     {
-        if !thr_context
-            .remote_list
-            .lock()
-            .unwrap()
-            .insert(expected_path.clone())
-        {
+        let mut remote_list = thr_context.remote_list.lock().unwrap();
+        if !remote_list.insert(expected_path.clone()) {
             // It is possible that multiple tasks might download the same file
             // (generated by apt/yum parser, etc.)
             // skip when we find that some threads has already downloaded it
             info!("Skipping already handled {:?}", &expected_path);
             return;
         }
+        // Local one of rewritten file, the same as expected path otherwise
+        remote_list.insert(local_file.clone());
+        // Files from extensions might be in directories never listed
+        for dir in expected_path.ancestors().skip(1) {
+            if !dir.starts_with(thr_context.download_dir) || !remote_list.insert(dir.to_path_buf())
+            {
+                break;
+            }
+        }
     }
 
     let mut should_download = true;
-    let mut skip_if_exists = false;
-    for i in &args.skip_if_exists {
-        if i.is_match(&relative_filepath) {
-            skip_if_exists = true;
-            break;
-        }
-    }
+    // Whether local file is confirmed up-to-date by listing or HEAD, to be recorded in state
+    let mut confirmed = false;
+    let skip_if_exists = args
+        .skip_if_exists
+        .iter()
+        .any(|i| i.is_match(&relative_filepath));
 
+    // Metadata from extensions (like Packages listed in Release) could change without changing size,
+    // so its checksum is always verified
+    let verify_checksum = args.verify_checksum
+        || thr_context
+            .extensions
+            .iter()
+            .any(|e| e.is_metadata(&relative_filepath));
     // Following code requires real filesystem path (expected_path) to work
     if !should_download_by_list(
         &expected_path,
@@ -386,20 +882,34 @@ fn download_handler(
         task_context.timezone,
         skip_if_exists,
         false,
+        verify_checksum,
     ) {
         info!("Skipping {}", task.url);
         should_download = false;
+        confirmed = true;
     }
 
-    let mut compare_size_only = false;
-    for i in &args.compare_size_only {
-        if i.is_match(&expected_path.to_string_lossy()) {
-            compare_size_only = true;
-            break;
-        }
+    let confirmed_by_state = thr_context
+        .state
+        .and_then(|s| s.get(&relative_filepath))
+        .is_some_and(|entry| !should_download_by_state(&expected_path, item, &entry));
+    if should_download && confirmed_by_state {
+        info!("Skipping (by state) {}", task.url);
+        should_download = false;
     }
 
-    if should_download && args.head_before_get {
+    let compare_size_only = args
+        .compare_size_only
+        .iter()
+        .any(|i| i.is_match(&expected_path.to_string_lossy()));
+
+    let is_ftp = item.url.scheme() == "ftp";
+    if should_download && args.head_before_get && is_ftp {
+        debug!(
+            "HEAD is not available for FTP, skipping HEAD of {}",
+            task.url
+        );
+    } else if should_download && args.head_before_get {
         match again(
             || head(task_context.blocking_client, item.url.clone()),
             args.retry,
@@ -408,6 +918,7 @@ fn download_handler(
                 if !should_download_by_head(&expected_path, &resp, compare_size_only) {
                     info!("Skipping (by HEAD) {}", task.url);
                     should_download = false;
+                    confirmed = true;
                 }
             }
             Err(e) => {
@@ -420,33 +931,53 @@ fn download_handler(
         };
     }
 
+    let mut local_path = expected_path.clone();
     if should_download && !args.dry_run {
-        let future = async {
-            if (download_file(
-                async_context.async_client,
-                item,
-                &expected_path,
-                args,
-                async_context.mprogress,
-                task_context.timezone,
-                cwd,
-            )
-            .await)
-                .is_err()
-            {
-                thr_context
-                    .failure_downloading
-                    .store(true, Ordering::SeqCst);
-            }
-        };
-        async_context.runtime.block_on(future);
+        local_path = download_and_record(
+            item,
+            &expected_path,
+            &relative_filepath,
+            args,
+            thr_context,
+            task_context,
+            async_context,
+        );
     } else if should_download {
         info!("Dry run, not downloading {}", task.url);
+    } else if confirmed {
+        record_state(thr_context.state, &relative_filepath, item, &expected_path);
     }
 
-    extension_handler(args, &expected_path, &task.relative, &item.url, |package| {
-        extension_push_task(task_context.worker, task_context.wake, package);
-    });
+    let parsed = extension_handler(
+        thr_context.extensions,
+        &local_path,
+        &task.relative,
+        &item.url,
+        |package| {
+            let path = thr_context
+                .download_dir
+                .join(package.relative.join("/"))
+                .join(&package.filename);
+            if let Some(references) = thr_context.references {
+                references.packages.lock().unwrap().insert(path.clone());
+            }
+            if thr_context.queued.lock().unwrap().insert(path) {
+                extension_push_task(task_context.worker, task_context.wake, package);
+            }
+        },
+    );
+    if let Some(extension) = rewriter {
+        rewrite_local_file(
+            &**extension,
+            &local_path,
+            local_file,
+            &item.url,
+            thr_context,
+        );
+    }
+    if let (Some(references), Some(parsed)) = (thr_context.references, parsed) {
+        references.record_parsed(thr_context.extensions, &local_path, cwd, parsed);
+    }
 }
 
 fn sync_threads(args: &SyncArgs, parser: &dyn crate::parser::Parser, thr_context: &ThreadsContext) {
@@ -472,7 +1003,12 @@ fn sync_threads(args: &SyncArgs, parser: &dyn crate::parser::Parser, thr_context
         1,
     ));
 
-    let timezone = determinate_timezone(args, parser, &client);
+    // Nothing would be listed when starting with seeds
+    let timezone = if thr_context.seeds.is_empty() {
+        determinate_timezone(args, parser, &client)
+    } else {
+        None
+    };
 
     if !args.dry_run {
         std::fs::create_dir_all(thr_context.download_dir).unwrap();
@@ -484,11 +1020,26 @@ fn sync_threads(args: &SyncArgs, parser: &dyn crate::parser::Parser, thr_context
     let stealers: Vec<_> = workers.iter().map(|w| w.stealer()).collect();
     let global = Injector::<Task>::new();
 
-    global.push(Task {
-        task: TaskType::Listing,
-        relative: vec![],
-        url: args.upstream.clone(),
-    });
+    if thr_context.seeds.is_empty() {
+        global.push(Task {
+            task: TaskType::Listing,
+            relative: vec![],
+            url: args.upstream.clone(),
+        });
+    } else {
+        // Upstream without listing (like Nix binary cache)
+        let mut queued = thr_context.queued.lock().unwrap();
+        for package in thr_context.seeds {
+            if queued.insert(
+                thr_context
+                    .download_dir
+                    .join(package.relative.join("/"))
+                    .join(&package.filename),
+            ) {
+                global.push(package_task(package));
+            }
+        }
+    }
 
     let active_cnt = AtomicUsize::new(0);
     let wake = AtomicUsize::new(0);
@@ -496,7 +1047,7 @@ fn sync_threads(args: &SyncArgs, parser: &dyn crate::parser::Parser, thr_context
     std::thread::scope(|scope| {
         for worker in workers {
             scope.spawn(|| {
-                loop {
+                'work: loop {
                     active_cnt.fetch_add(1, Ordering::SeqCst);
                     while let Some(task) = worker.pop().or_else(|| {
                         std::iter::repeat_with(|| {
@@ -574,6 +1125,9 @@ fn sync_threads(args: &SyncArgs, parser: &dyn crate::parser::Parser, thr_context
                                 {
                                     break;
                                 }
+                            } else if active_cnt.load(Ordering::SeqCst) == 0 {
+                                // All others have finished, and no one would wake us up
+                                break 'work;
                             }
                         }
                     }
@@ -586,9 +1140,181 @@ fn sync_threads(args: &SyncArgs, parser: &dyn crate::parser::Parser, thr_context
     });
 }
 
+/// Remove local files that are not in remote list. Returns exit code.
+/// With --no-delete, only unreferenced packages (by --delete-unreferenced) are removed.
+fn delete_not_in_remote(
+    args: &SyncArgs,
+    download_dir: &Path,
+    remote_list: &HashSet<PathBuf>,
+    unreferenced: &HashSet<PathBuf>,
+) -> i32 {
+    let mut exit_code = 0;
+    let mut del_cnt = 0;
+    // Don't even walkdir when dry_run, to prevent no dir error
+    for entry in walkdir::WalkDir::new(download_dir).contents_first(true) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                error!("Failed to walkdir: {:?}", e);
+                if !args.dry_run {
+                    exit_code = 1;
+                }
+                break;
+            }
+        };
+        let path = entry.path();
+        if !remote_list.contains(&path.to_path_buf()) {
+            if args.no_delete && !unreferenced.contains(path) {
+                info!("{:?} not in remote", path);
+            } else {
+                // always make sure that we are deleting the right thing
+                if del_cnt >= args.max_delete {
+                    info!("Exceeding max delete count, aborting");
+                    // exit with 25 to indicate that the deletion has been aborted
+                    // this is the same as rsync
+                    exit_code = 25;
+                    break;
+                }
+                del_cnt += 1;
+                assert!(path.starts_with(download_dir));
+                if args.dry_run {
+                    info!("Dry run, not deleting {:?}", path);
+                    continue;
+                }
+
+                info!("Deleting {:?}", path);
+                if entry.file_type().is_dir() {
+                    if let Err(e) = std::fs::remove_dir(path) {
+                        error!("Failed to remove {:?}: {:?}", path, e);
+                        exit_code = 4;
+                    }
+                } else if let Err(e) = std::fs::remove_file(path) {
+                    error!("Failed to remove {:?}: {:?}", path, e);
+                    exit_code = 4;
+                }
+            }
+        }
+    }
+    exit_code
+}
+
+/// Show changes of this run and save the state.
+fn report_and_save_state(args: &SyncArgs, state: &SyncState) -> Result<()> {
+    let diff = state.diff();
+    for path in &diff.added {
+        debug!("State added: {}", path);
+    }
+    for path in &diff.updated {
+        debug!("State updated: {}", path);
+    }
+    for path in &diff.removed {
+        debug!("State removed: {}", path);
+    }
+    info!(
+        "State changes: {} added, {} updated, {} removed",
+        diff.added.len(),
+        diff.updated.len(),
+        diff.removed.len()
+    );
+    if args.dry_run {
+        info!("Dry run, not saving state");
+        return Ok(());
+    }
+    state.save()
+}
+
+/// Files to start with when upstream cannot be listed (--nix-store-paths). Exits on error.
+fn build_seeds(args: &SyncArgs) -> Vec<ExtensionPackage> {
+    if args.nix_store_paths.is_empty() {
+        return vec![];
+    }
+    match nix::seed_packages(&args.nix_store_paths, &args.upstream) {
+        Ok(seeds) => seeds,
+        Err(e) => {
+            error!("Failed to load Nix store paths: {:?}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Extensions enabled by --extension, legacy --apt-packages and --yum-packages, and --nix-store-paths.
+fn build_extensions(args: &SyncArgs) -> Vec<Box<dyn RepoExtension>> {
+    let mut types = args.extension.clone();
+    if args.apt_packages {
+        types.push(ExtensionType::Apt);
+    }
+    if args.yum_packages {
+        types.push(ExtensionType::Yum);
+    }
+    if !args.nix_store_paths.is_empty() {
+        types.push(ExtensionType::Nix);
+    }
+    let mut extensions: Vec<Box<dyn RepoExtension>> = vec![];
+    for (i, type_) in types.iter().enumerate() {
+        if !types[..i].contains(type_) {
+            extensions.push(type_.build());
+        }
+    }
+    extensions
+}
+
+/// Publish staged metadata if everything else is synced and signatures are good,
+/// otherwise discard them. Returns exit code (0 if published).
+fn publish_metadata(staging: &Staging, keyring: Option<&Keyring>, failed: bool) -> i32 {
+    if failed {
+        error!("Sync is incomplete, keeping old metadata");
+        staging.discard();
+        return 2;
+    }
+    if let Some(Err(e)) = keyring.map(|k| staging.verify(k)) {
+        error!(
+            "Signature verification failed, keeping old metadata: {:?}",
+            e
+        );
+        staging.discard();
+        return 5;
+    }
+    match staging.publish() {
+        Ok(()) => 0,
+        Err(e) => {
+            error!("Failed to publish metadata: {:?}", e);
+            4
+        }
+    }
+}
+
+/// Publish staged metadata (if any), and then local files which might refer to it.
+/// Local files are not written if sync is incomplete, as they might refer to missing files.
+fn publish(
+    args: &SyncArgs,
+    staging: Option<&Staging>,
+    keyring: Option<&Keyring>,
+    local_files: &LocalFiles,
+    failed: bool,
+) -> i32 {
+    let code = staging.map_or(0, |staging| publish_metadata(staging, keyring, failed));
+    if code != 0 {
+        return code;
+    }
+    if failed {
+        error!("Sync is incomplete, not writing local files");
+        return 0;
+    }
+    if !local_files.write(args.dry_run) {
+        return 4;
+    }
+    0
+}
+
 pub fn sync(args: &SyncArgs, bind_address: Option<String>) -> ! {
     debug!("{:?}", args);
-    let parser = args.parser.build();
+    let parser = match args.parser.build(args.parser_config.as_deref()) {
+        Ok(parser) => parser,
+        Err(e) => {
+            error!("Failed to build parser: {:?}", e);
+            std::process::exit(1);
+        }
+    };
 
     let download_dir = args.local.as_path();
 
@@ -600,6 +1326,42 @@ pub fn sync(args: &SyncArgs, bind_address: Option<String>) -> ! {
     let failure_listing = AtomicBool::new(false);
     let failure_downloading = AtomicBool::new(false);
 
+    let state = match &args.state_file {
+        Some(path) => match SyncState::load(path) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                error!("Failed to load state from {:?}: {:?}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let keyring = if args.keyring.is_empty() {
+        None
+    } else {
+        match Keyring::load(&args.keyring) {
+            Ok(keyring) => Some(keyring),
+            Err(e) => {
+                error!("Failed to load keyring: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    // Metadata is staged to be verified before publishing
+    let staging = ((args.metadata_last || keyring.is_some()) && !args.dry_run)
+        .then(|| Staging::new(download_dir, &args.metadata_regex));
+    if let Some(staging) = &staging {
+        // Leftover of an interrupted run
+        staging.discard();
+    }
+
+    let extensions = build_extensions(args);
+    let references = args.delete_unreferenced.then(References::default);
+    let seeds = build_seeds(args);
+    let local_files = LocalFiles::default();
+
     sync_threads(
         args,
         &*parser,
@@ -611,62 +1373,50 @@ pub fn sync(args: &SyncArgs, bind_address: Option<String>) -> ! {
             stat_size: &stat_size,
             failure_listing: &failure_listing,
             failure_downloading: &failure_downloading,
+            state: state.as_ref(),
+            staging: staging.as_ref(),
+            extensions: &extensions,
+            references: references.as_ref(),
+            seeds: &seeds,
+            queued: &Mutex::new(HashSet::new()),
+            local_files: &local_files,
         },
     );
 
-    let mut exit_code = 0;
-
     // Removing files that are not in remote list
-    let mut del_cnt = 0;
-    let remote_list = remote_list.lock().unwrap();
-    if failure_listing.load(Ordering::SeqCst) {
+    let mut remote_list = remote_list.lock().unwrap();
+    if let Some(state) = &state {
+        // Keep the state file if it is inside download dir
+        remote_list.insert(state.path().to_path_buf());
+    }
+    let publish_code = publish(
+        args,
+        staging.as_ref(),
+        keyring.as_ref(),
+        &local_files,
+        failure_listing.load(Ordering::SeqCst) || failure_downloading.load(Ordering::SeqCst),
+    );
+    let mut exit_code = if failure_listing.load(Ordering::SeqCst) {
         error!("Failed to list remote, not to delete anything");
-        exit_code = 1;
+        1
+    } else if publish_code != 0 {
+        // Old metadata might still refer to files to delete
+        error!("Metadata is not published, not to delete anything");
+        publish_code
     } else {
-        // Don't even walkdir when dry_run, to prevent no dir error
-        for entry in walkdir::WalkDir::new(download_dir).contents_first(true) {
-            let entry = match entry {
-                Ok(entry) => entry,
-                Err(e) => {
-                    error!("Failed to walkdir: {:?}", e);
-                    if !args.dry_run {
-                        exit_code = 1;
-                    }
-                    break;
-                }
-            };
-            let path = entry.path();
-            if !remote_list.contains(&path.to_path_buf()) {
-                if args.no_delete {
-                    info!("{:?} not in remote", path);
-                } else {
-                    // always make sure that we are deleting the right thing
-                    if del_cnt >= args.max_delete {
-                        info!("Exceeding max delete count, aborting");
-                        // exit with 25 to indicate that the deletion has been aborted
-                        // this is the same as rsync
-                        exit_code = 25;
-                        break;
-                    }
-                    del_cnt += 1;
-                    assert!(path.starts_with(download_dir));
-                    if args.dry_run {
-                        info!("Dry run, not deleting {:?}", path);
-                        continue;
-                    }
+        let unreferenced = references.as_ref().map_or_else(HashSet::new, |references| {
+            references.drop_unreferenced(&extensions, download_dir, &mut remote_list)
+        });
+        if let Some(state) = &state {
+            state.retain(download_dir, &remote_list);
+        }
+        delete_not_in_remote(args, download_dir, &remote_list, &unreferenced)
+    };
 
-                    info!("Deleting {:?}", path);
-                    if entry.file_type().is_dir() {
-                        if let Err(e) = std::fs::remove_dir(path) {
-                            error!("Failed to remove {:?}: {:?}", path, e);
-                            exit_code = 4;
-                        }
-                    } else if let Err(e) = std::fs::remove_file(path) {
-                        error!("Failed to remove {:?}: {:?}", path, e);
-                        exit_code = 4;
-                    }
-                }
-            }
+    if let Some(state) = &state {
+        if let Err(e) = report_and_save_state(args, state) {
+            error!("Failed to save state to {:?}: {:?}", state.path(), e);
+            exit_code = 4;
         }
     }
 
@@ -675,6 +1425,11 @@ pub fn sync(args: &SyncArgs, bind_address: Option<String>) -> ! {
         exit_code = 2;
     }
 
+    if publish_code == 5 {
+        // Bad or missing signature is never hidden by other failures
+        exit_code = 5;
+    }
+
     // Show stat
     info!(
         "(Estimated) Total objects: {}, total size: {}",
@@ -687,7 +1442,7 @@ pub fn sync(args: &SyncArgs, bind_address: Option<String>) -> ! {
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
 
     #[test]
     fn test_relative() {
@@ -698,4 +1453,59 @@ mod tests {
         relative.push("dists".to_string());
         assert_eq!(relative.join("/"), "debian/dists");
     }
+
+    #[test]
+    fn test_drop_unreferenced() {
+        let download_dir = Path::new("/srv/conda");
+        let extensions = vec![ExtensionType::Conda.build()];
+        let references = References::default();
+        let paths = [
+            "main/linux-64/repodata.json",
+            "main/linux-64/a-1.0-0.conda",
+            "main/linux-64/a-0.9-0.conda",
+            "main/linux-64/.tmp.a-1.1-0.conda",
+            "main/noarch/b-0.1-0.tar.bz2",
+            "main/osx-64/c-0.1-0.conda",
+        ]
+        .map(|p| download_dir.join(p));
+        let mut remote_list: HashSet<PathBuf> = paths.iter().cloned().collect();
+        references.packages.lock().unwrap().insert(paths[1].clone());
+        let mut dirs = references.dirs.lock().unwrap();
+        dirs.insert(download_dir.join("main/linux-64"), true);
+        // Metadata is not parsed in osx-64
+        dirs.insert(download_dir.join("main/osx-64"), false);
+        drop(dirs);
+
+        let unreferenced =
+            references.drop_unreferenced(&extensions, download_dir, &mut remote_list);
+        assert_eq!(unreferenced, HashSet::from([paths[2].clone()]));
+        let mut expected: HashSet<PathBuf> = paths.iter().cloned().collect();
+        expected.remove(&paths[2]);
+        assert_eq!(remote_list, expected);
+    }
+
+    #[test]
+    fn test_drop_unreferenced_nested() {
+        let download_dir = Path::new("/srv/yum");
+        let extensions = vec![ExtensionType::Yum.build()];
+        let references = References::default();
+        let paths = [
+            "x86_64/Packages/a-1.0.rpm",
+            "x86_64/Packages/a-0.9.rpm",
+            "x86_64/debug/Packages/a-debuginfo-1.0.rpm",
+        ]
+        .map(|p| download_dir.join(p));
+        let mut remote_list: HashSet<PathBuf> = paths.iter().cloned().collect();
+        references.packages.lock().unwrap().insert(paths[0].clone());
+        let mut dirs = references.dirs.lock().unwrap();
+        dirs.insert(download_dir.join("x86_64"), true);
+        // Nested repository fails to parse, though its parent is fine
+        dirs.insert(download_dir.join("x86_64/debug"), false);
+        drop(dirs);
+
+        let unreferenced =
+            references.drop_unreferenced(&extensions, download_dir, &mut remote_list);
+        assert_eq!(unreferenced, HashSet::from([paths[1].clone()]));
+        assert!(remote_list.contains(&paths[2]));
+    }
 }
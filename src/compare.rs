@@ -4,7 +4,9 @@ use chrono::{DateTime, FixedOffset, Utc};
 use tracing::{debug, warn};
 
 use crate::{
+    checksum::verify_file,
     listing::{FileSize, FileType, ListItem},
+    state::StateEntry,
     utils::{self, naive_to_utc},
 };
 
@@ -15,15 +17,39 @@ pub fn compare_filetype(fstype: std::fs::FileType, tsumugu_type: FileType) -> bo
     }
 }
 
+/// Check existing package (from APT/YUM metadata, etc.) with size, and checksum if verify_checksum is set.
+fn is_package_intact(path: &Path, remote: &ListItem, verify_checksum: bool) -> bool {
+    let size = match remote.size {
+        Some(FileSize::Precise(size)) => Some(size),
+        _ => None,
+    };
+    let checksum = if verify_checksum {
+        remote.checksum.as_ref()
+    } else {
+        None
+    };
+    match verify_file(path, size, checksum) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("{:?}, repairing", e);
+            false
+        }
+    }
+}
+
 pub fn should_download_by_list(
     path: &Path,
     remote: &ListItem,
     remote_timezone: Option<FixedOffset>,
     skip_if_exists: bool,
     size_only: bool,
+    verify_checksum: bool,
 ) -> bool {
     let local_metadata = match path.metadata() {
         Ok(m) => {
+            if remote.skip_check && !skip_if_exists {
+                return !is_package_intact(path, remote, verify_checksum);
+            }
             if skip_if_exists || remote.skip_check {
                 debug!("Skipping {:?} because it exists", path);
                 return false;
@@ -76,6 +102,7 @@ pub fn should_download_by_list(
         }
     }
     .into();
+    let remote_timezone = remote.timezone.or(remote_timezone);
     let remote_mtime = naive_to_utc(&remote.mtime, remote_timezone);
     let offset = remote_mtime - local_mtime;
     debug!("DateTime offset: {:?} {:?}", path, offset);
@@ -91,6 +118,19 @@ pub fn should_download_by_list(
     }
 }
 
+/// A file needs no further check if neither remote nor local has changed since it is last verified.
+pub fn should_download_by_state(path: &Path, remote: &ListItem, state: &StateEntry) -> bool {
+    if !state.matches_remote(remote) {
+        debug!("Remote changed since last verified: {:?}", path);
+        return true;
+    }
+    if !state.matches_local(path) {
+        debug!("Local changed since last verified: {:?}", path);
+        return true;
+    }
+    false
+}
+
 pub fn should_download_by_head(
     path: &Path,
     resp: &reqwest::blocking::Response,
@@ -118,7 +158,82 @@ pub fn should_download_by_head(
         mtime: utils::get_blocking_response_mtime(resp)
             .unwrap()
             .naive_utc(),
+        timezone: FixedOffset::east_opt(0),
+        etag: None,
+        checksum: None,
         skip_check: false,
+        optional: false,
+        attributes: vec![],
     };
-    should_download_by_list(path, &item, FixedOffset::east_opt(0), false, size_only)
+    should_download_by_list(
+        path,
+        &item,
+        FixedOffset::east_opt(0),
+        false,
+        size_only,
+        false,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use url::Url;
+
+    use super::*;
+
+    #[test]
+    fn test_package_repair() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/checksum/hello.txt");
+        let size = path.metadata().unwrap().len();
+        let mut item = ListItem::new(
+            Url::parse("http://localhost/hello.txt").unwrap(),
+            "hello.txt".to_string(),
+            FileType::File,
+            Some(FileSize::Precise(size)),
+            NaiveDateTime::default(),
+        );
+        item.skip_check = true;
+        item.checksum = Some(crate::checksum::Checksum::Sha256("0".repeat(64)));
+        // checksum is only checked when asked to
+        assert!(!should_download_by_list(
+            &path, &item, None, false, false, false
+        ));
+        assert!(should_download_by_list(
+            &path, &item, None, false, false, true
+        ));
+        item.size = Some(FileSize::Precise(size + 1));
+        assert!(should_download_by_list(
+            &path, &item, None, false, false, false
+        ));
+        // skip_if_exists from user wins
+        assert!(!should_download_by_list(
+            &path, &item, None, true, false, true
+        ));
+    }
+
+    #[test]
+    fn test_state_checksum() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/checksum/hello.txt");
+        let mut item = ListItem::new(
+            Url::parse("http://localhost/hello.txt").unwrap(),
+            "hello.txt".to_string(),
+            FileType::File,
+            Some(FileSize::Precise(path.metadata().unwrap().len())),
+            NaiveDateTime::default(),
+        );
+        item.skip_check = true;
+        item.checksum = crate::checksum::Checksum::new(
+            "sha256",
+            "aaa6f42566aff305128b040931addd235c250d86bf5f89495099d5d8b7d74998",
+        );
+        let entry = StateEntry::new(&item, &path).unwrap();
+        assert!(!should_download_by_state(&path, &item, &entry));
+        // Metadata changes checksum only, keeping size (and no mtime from extensions)
+        item.checksum = Some(crate::checksum::Checksum::Sha256("0".repeat(64)));
+        assert!(should_download_by_list(
+            &path, &item, None, false, false, true
+        ));
+        assert!(should_download_by_state(&path, &item, &entry));
+    }
 }
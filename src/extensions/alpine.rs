@@ -0,0 +1,140 @@
+// Alpine repos: APKINDEX.tar.gz in each <repo>/<arch>/ lists packages next to it.
+// The index is made of concatenated gzip streams: a signature tar, and a tar with APKINDEX and DESCRIPTION.
+
+use std::{fs::File, io::Read, path::Path};
+
+use anyhow::{anyhow, Result};
+use flate2::read::MultiGzDecoder;
+use url::Url;
+
+use super::ExtensionPackage;
+
+#[derive(Debug, PartialEq)]
+struct ApkIndexEntry {
+    name: String,
+    version: String,
+    size: Option<u64>,
+}
+
+fn is_apkindex(p: &Path) -> bool {
+    p.file_name().is_some_and(|f| f == "APKINDEX.tar.gz")
+}
+
+fn read_apkindex(p: &Path) -> Result<String> {
+    let mut archive = tar::Archive::new(MultiGzDecoder::new(File::open(p)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.as_os_str() == "APKINDEX" {
+            let mut s = String::new();
+            entry.read_to_string(&mut s)?;
+            return Ok(s);
+        }
+    }
+    Err(anyhow!("Cannot find APKINDEX in {:?}", p))
+}
+
+// Entries are separated by empty lines, with one "X:value" field per line.
+// C: is the SHA1 of control segment rather than the whole .apk, so only size (S:) is used to verify.
+fn parse_apkindex(data: &str) -> Vec<ApkIndexEntry> {
+    data.split("\n\n")
+        .filter_map(|block| {
+            let (mut name, mut version, mut size) = (None, None, None);
+            for line in block.lines() {
+                match line.split_once(':') {
+                    Some(("P", v)) => name = Some(v),
+                    Some(("V", v)) => version = Some(v),
+                    Some(("S", v)) => size = v.parse().ok(),
+                    _ => {}
+                }
+            }
+            Some(ApkIndexEntry {
+                name: name?.to_string(),
+                version: version?.to_string(),
+                size,
+            })
+        })
+        .collect()
+}
+
+pub struct AlpineExtension;
+
+impl super::RepoExtension for AlpineExtension {
+    fn name(&self) -> &'static str {
+        "Alpine"
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        is_apkindex(path)
+    }
+
+    fn parse(&self, path: &Path, relative: &[String], url: &Url) -> Result<Vec<ExtensionPackage>> {
+        let mut res = vec![];
+        for entry in parse_apkindex(&read_apkindex(path)?) {
+            let filename = format!("{}-{}.apk", entry.name, entry.version);
+            let mut url = url.clone();
+            url.path_segments_mut()
+                .map_err(|_| anyhow!("URL cannot be a base"))?
+                .pop()
+                .push(&filename);
+            res.push(ExtensionPackage {
+                url,
+                relative: relative.to_vec(),
+                filename,
+                size: entry.size,
+                checksum: None,
+                optional: false,
+            });
+        }
+        Ok(res)
+    }
+
+    fn is_metadata(&self, relative: &str) -> bool {
+        is_apkindex(Path::new(relative))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::extensions::RepoExtension;
+
+    #[test]
+    fn test_apkindex() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/alpine/v3.19/main/x86_64/APKINDEX.tar.gz");
+        assert!(AlpineExtension.matches(&path));
+        let entries = parse_apkindex(&read_apkindex(&path).unwrap());
+        assert_eq!(
+            entries,
+            vec![
+                ApkIndexEntry {
+                    name: "musl".to_string(),
+                    version: "1.2.4_git20230717-r4".to_string(),
+                    size: Some(407884),
+                },
+                ApkIndexEntry {
+                    name: "zlib".to_string(),
+                    version: "1.3.1-r0".to_string(),
+                    size: Some(53346),
+                },
+            ]
+        );
+
+        let relative: Vec<String> = ["v3.19", "main", "x86_64"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let url = Url::parse("http://localhost/alpine/v3.19/main/x86_64/APKINDEX.tar.gz").unwrap();
+        let packages = AlpineExtension.parse(&path, &relative, &url).unwrap();
+        assert_eq!(packages.len(), 2);
+        assert_eq!(
+            packages[1].url.as_str(),
+            "http://localhost/alpine/v3.19/main/x86_64/zlib-1.3.1-r0.apk"
+        );
+        assert_eq!(packages[1].relative, relative);
+        assert_eq!(packages[1].filename, "zlib-1.3.1-r0.apk");
+        assert_eq!(packages[1].size, Some(53346));
+    }
+}
@@ -1,23 +1,106 @@
 use anyhow::Result;
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use super::read_compressed;
+use crate::checksum::Checksum;
 use tracing::warn;
 use url::Url;
 
-pub fn is_apt_package(p: &Path) -> bool {
-    // check if basename is Packages
-    let basename = p.file_name().unwrap().to_str().unwrap();
-    if basename != "Packages" {
-        return false;
-    }
-    // check if parents contain dists
-    let parents = p.ancestors();
-    for iter in parents {
-        let basename = iter.file_name().unwrap().to_str().unwrap();
-        if basename == "dists" {
-            return true;
+enum AptIndexType {
+    /// InRelease or Release, listing all other index files
+    Release,
+    Packages,
+    Sources,
+}
+
+const COMPRESSIONS: &[&str] = &["gz", "xz", "bz2", "lzma", "zst"];
+
+fn get_apt_index_type(p: &Path) -> Option<AptIndexType> {
+    let basename = p.file_name()?.to_str()?;
+    let index_type = match basename.split_once('.') {
+        None if basename == "InRelease" || basename == "Release" => AptIndexType::Release,
+        None if basename == "Packages" => AptIndexType::Packages,
+        None if basename == "Sources" => AptIndexType::Sources,
+        Some(("Packages", ext)) if COMPRESSIONS.contains(&ext) => AptIndexType::Packages,
+        Some(("Sources", ext)) if COMPRESSIONS.contains(&ext) => AptIndexType::Sources,
+        _ => return None,
+    };
+    // Not only under dists: flat repos have index files next to packages
+    Some(index_type)
+}
+
+/// Remove OpenPGP cleartext signature framework around InRelease.
+fn strip_signature(data: &str) -> &str {
+    let Some(rest) = data.strip_prefix("-----BEGIN PGP SIGNED MESSAGE-----") else {
+        return data;
+    };
+    // Armor headers end with an empty line
+    let rest = rest.split_once("\n\n").map(|(_, r)| r).unwrap_or(rest);
+    rest.split("-----BEGIN PGP SIGNATURE-----").next().unwrap()
+}
+
+/// A deb822 paragraph (stanza). Values of multiline fields keep their line breaks.
+#[derive(Debug, Default)]
+pub(super) struct Paragraph<'a>(Vec<(&'a str, String)>);
+
+impl Paragraph<'_> {
+    /// Get value of field, whose name is case-insensitive.
+    pub(super) fn get(&self, field: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(f, _)| f.eq_ignore_ascii_case(field))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+pub(super) fn parse_paragraphs(data: &str) -> Vec<Paragraph<'_>> {
+    let mut paragraphs = vec![];
+    let mut current = Paragraph::default();
+    for line in data.lines() {
+        if line.trim().is_empty() {
+            if !current.0.is_empty() {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+        } else if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = current.0.last_mut() {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+        } else if let Some((field, value)) = line.split_once(':') {
+            current.0.push((field, value.trim().to_string()));
         }
     }
-    false
+    if !current.0.is_empty() {
+        paragraphs.push(current);
+    }
+    paragraphs
+}
+
+/// Parse lines like "<checksum> <size> <path>" in checksum fields.
+/// The first field found in `fields` is used, and checksums are taken as algorithm of `fields[i].1`.
+fn parse_checksum_field(
+    paragraph: &Paragraph,
+    fields: &[(&str, &str)],
+) -> Vec<(String, u64, Option<Checksum>)> {
+    let Some((value, algorithm)) = fields
+        .iter()
+        .find_map(|(f, a)| paragraph.get(f).map(|v| (v, a)))
+    else {
+        return vec![];
+    };
+    value
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let checksum = Checksum::new(algorithm, parts.next()?);
+            let size = parts.next()?.parse().ok()?;
+            let path = parts.next()?.to_string();
+            Some((path, size, checksum))
+        })
+        .collect()
 }
 
 // In every iter packages_path and packages_url be updated to their parents
@@ -59,7 +142,7 @@ fn get_debian_root(
     pop(&mut packages_path, None, &mut packages_url)?;
     loop {
         let basename = packages_path.file_name().unwrap().to_str().unwrap();
-        let url_basename = packages_url.path_segments().unwrap().last().unwrap();
+        let url_basename = packages_url.path_segments().unwrap().next_back().unwrap();
         if basename == "dists" && url_basename == "dists" {
             // we don't wanna dists folder in return value
             pop(&mut packages_path, Some(&mut relative), &mut packages_url)?;
@@ -78,10 +161,11 @@ fn get_debian_root(
 }
 
 #[derive(Debug)]
-pub struct AptPackage {
+struct AptPackage {
     pub url: Url,
     pub relative: Vec<String>,
-    pub size: usize,
+    pub size: u64,
+    pub checksum: Option<Checksum>,
     pub filename: String,
 }
 
@@ -91,48 +175,412 @@ impl From<AptPackage> for super::ExtensionPackage {
             url: val.url,
             relative: val.relative,
             filename: val.filename,
+            size: Some(val.size),
+            checksum: val.checksum,
+            optional: false,
         }
     }
 }
 
-pub fn parse_package(
-    packages_path: &Path,
+/// Build package at `path` relative to `base_url` and `base_relative`.
+fn make_package(
+    base_url: &Url,
+    base_relative: &[String],
+    path: &str,
+    size: u64,
+    checksum: Option<Checksum>,
+) -> Result<AptPackage> {
+    let url = base_url.join(path)?;
+    // Flat repos might use paths like "./foo.deb"
+    let mut segments: Vec<String> = path
+        .split('/')
+        .filter(|s| !s.is_empty() && *s != ".")
+        .map(|s| s.to_string())
+        .collect();
+    let filename = segments
+        .pop()
+        .ok_or_else(|| anyhow::anyhow!("Invalid path in index: {:?}", path))?;
+    let mut relative = base_relative.to_vec();
+    relative.append(&mut segments);
+    Ok(AptPackage {
+        url,
+        relative,
+        size,
+        checksum,
+        filename,
+    })
+}
+
+fn parse_index(
+    index_path: &Path,
     relative: &[String],
-    packages_url: &Url,
+    index_url: &Url,
+    index_type: AptIndexType,
 ) -> Result<Vec<AptPackage>> {
-    let data = std::fs::read_to_string(packages_path)?;
-    let packages = apt_parser::Packages::from(&data);
-    let (_, root_relative, debian_root_url) =
-        get_debian_root(packages_path, relative, packages_url)?;
-    // ignore errors
+    let data = read_compressed(index_path)?;
+    match index_type {
+        AptIndexType::Release => parse_release(&data, relative, index_url),
+        AptIndexType::Packages => {
+            let (root_relative, root_url) = get_apt_root(index_path, relative, index_url)?;
+            parse_packages(&data, &root_relative, &root_url)
+        }
+        AptIndexType::Sources => {
+            let (root_relative, root_url) = get_apt_root(index_path, relative, index_url)?;
+            parse_sources(&data, &root_relative, &root_url)
+        }
+    }
+}
+
+/// Root which paths in Packages and Sources are relative to.
+/// It is the parent of dists, or the directory of index file for flat repos.
+fn get_apt_root(
+    index_path: &Path,
+    relative: &[String],
+    index_url: &Url,
+) -> Result<(Vec<String>, Url)> {
+    let in_dists = index_url
+        .path_segments()
+        .is_some_and(|mut s| s.any(|s| s == "dists"));
+    if in_dists {
+        let (_, root_relative, root_url) = get_debian_root(index_path, relative, index_url)?;
+        Ok((root_relative, root_url))
+    } else {
+        Ok((relative.to_vec(), index_url.join("./")?))
+    }
+}
+
+/// Index files listed in Release, relative to the directory of Release.
+fn parse_release(data: &str, relative: &[String], release_url: &Url) -> Result<Vec<AptPackage>> {
+    let paragraphs = parse_paragraphs(strip_signature(data));
+    let Some(release) = paragraphs.first() else {
+        return Ok(vec![]);
+    };
+    let mut files = parse_checksum_field(
+        release,
+        &[
+            ("SHA256", "sha256"),
+            ("SHA512", "sha512"),
+            ("MD5Sum", "md5"),
+        ],
+    );
+    // Uncompressed index files are listed but usually not available, if compressed ones exist
+    let listed: HashSet<String> = files.iter().map(|(path, _, _)| path.clone()).collect();
+    files.retain(|(path, _, _)| {
+        !COMPRESSIONS
+            .iter()
+            .any(|ext| listed.contains(&format!("{path}.{ext}")))
+    });
+    let by_hash = release.get("Acquire-By-Hash").is_some_and(|v| v == "yes");
+
+    let mut res = vec![];
+    for (path, size, checksum) in files {
+        if let (true, Some(Checksum::Sha256(digest))) = (by_hash, &checksum) {
+            let dir = path.rsplit_once('/').map(|(d, _)| d).unwrap_or(".");
+            let hashed = format!("{dir}/by-hash/SHA256/{digest}");
+            res.push(make_package(
+                release_url,
+                relative,
+                &hashed,
+                size,
+                checksum.clone(),
+            )?);
+        }
+        res.push(make_package(release_url, relative, &path, size, checksum)?);
+    }
+    Ok(res)
+}
+
+fn parse_packages(data: &str, root_relative: &[String], root_url: &Url) -> Result<Vec<AptPackage>> {
+    let packages = apt_parser::Packages::from(data);
+    if let Some(e) = packages.errors.first() {
+        warn!(
+            "{} packages cannot be parsed, first error: {:?}",
+            packages.errors.len(),
+            e
+        );
+    }
     let mut res = vec![];
     for package in packages {
-        let pool_url = package.filename;
-        let size = package.size;
-        let url = debian_root_url.join(&pool_url)?;
+        let checksum = package
+            .sha256sum
+            .as_deref()
+            .and_then(|s| Checksum::new("sha256", s));
+        res.push(make_package(
+            root_url,
+            root_relative,
+            &package.filename,
+            package.size as u64,
+            checksum,
+        )?);
+    }
+    Ok(res)
+}
 
-        let mut pool_splited: Vec<String> = pool_url.split('/').map(|s| s.to_string()).collect();
-        let mut relative = root_relative.clone();
-        relative.append(&mut pool_splited);
+/// Source package files (.dsc, .orig.tar.*, etc.) in pool.
+fn parse_sources(data: &str, root_relative: &[String], root_url: &Url) -> Result<Vec<AptPackage>> {
+    let mut res = vec![];
+    for paragraph in parse_paragraphs(data) {
+        let Some(directory) = paragraph.get("Directory") else {
+            warn!(
+                "Source package without Directory: {:?}",
+                paragraph.get("Package")
+            );
+            continue;
+        };
+        let files = parse_checksum_field(
+            &paragraph,
+            &[
+                ("Checksums-Sha256", "sha256"),
+                ("Checksums-Sha512", "sha512"),
+                ("Files", "md5"),
+            ],
+        );
+        let directory = directory.trim_end_matches('/');
+        for (name, size, checksum) in files {
+            let path = format!("{directory}/{name}");
+            res.push(make_package(
+                root_url,
+                root_relative,
+                &path,
+                size,
+                checksum,
+            )?);
+        }
+    }
+    Ok(res)
+}
 
-        let basename = relative.pop().unwrap();
+pub struct AptExtension;
 
-        res.push(AptPackage {
-            url,
-            relative,
-            size: size as usize,
-            filename: basename,
-        })
+impl super::RepoExtension for AptExtension {
+    fn name(&self) -> &'static str {
+        "APT"
     }
 
-    Ok(res)
+    fn matches(&self, path: &Path) -> bool {
+        get_apt_index_type(path).is_some()
+    }
+
+    fn parse(
+        &self,
+        path: &Path,
+        relative: &[String],
+        url: &Url,
+    ) -> Result<Vec<super::ExtensionPackage>> {
+        let index_type = get_apt_index_type(path)
+            .ok_or_else(|| anyhow::anyhow!("Not an APT index: {:?}", path))?;
+        Ok(parse_index(path, relative, url, index_type)?
+            .into_iter()
+            .map(|p| p.into())
+            .collect())
+    }
+
+    fn is_metadata(&self, relative: &str) -> bool {
+        relative.split('/').any(|s| s == "dists")
+            || get_apt_index_type(Path::new(relative)).is_some()
+    }
+
+    fn is_package(&self, relative: &str) -> bool {
+        relative.split('/').any(|s| s == "pool")
+    }
+
+    /// Packages in pool/ are shared by all dists/
+    fn package_root<'a>(&self, dir: &'a Path) -> &'a Path {
+        dir.ancestors()
+            .find(|d| d.file_name().is_some_and(|f| f == "dists"))
+            .and_then(|d| d.parent())
+            .unwrap_or(dir)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::extensions::RepoExtension;
     use test_log::test;
 
+    #[test]
+    fn test_release() {
+        let data = r#"-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA512
+
+Origin: Debian
+Suite: stable
+Codename: bookworm
+Acquire-By-Hash: yes
+MD5Sum:
+ 0ed6d4c8891eb86358b94bb35d9e4da4  1484322 contrib/Contents-all
+ d0a0325a97c42fd5f66a8c3e29bcea64    98581 contrib/Contents-all.gz
+SHA256:
+ d6c9c82f4e61b4662f9ba16b9ebb379c57b4943f8b7813091d1f637325ddfb79  1484322 contrib/Contents-all
+ 3e9a121d599b56c08bc8f144e4830807c77c29d7114316d6984ba54695d3db7b    98581 contrib/Contents-all.gz
+ f9e6ad1a4ed7ae2bf8ca5de5a0ef7ba2e9a1cc4d0c5a9b3e7a0d85b3b0a0f0a1      100 main/binary-amd64/Release
+-----BEGIN PGP SIGNATURE-----
+
+iQIzBAEBCgAdFiEE
+-----END PGP SIGNATURE-----
+"#;
+        let relative = vec!["dists".to_string(), "bookworm".to_string()];
+        let url = Url::parse("http://localhost/debian/dists/bookworm/InRelease").unwrap();
+        let files = parse_release(data, &relative, &url).unwrap();
+        let urls: Vec<&str> = files.iter().map(|f| f.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "http://localhost/debian/dists/bookworm/contrib/by-hash/SHA256/3e9a121d599b56c08bc8f144e4830807c77c29d7114316d6984ba54695d3db7b",
+                "http://localhost/debian/dists/bookworm/contrib/Contents-all.gz",
+                "http://localhost/debian/dists/bookworm/main/binary-amd64/by-hash/SHA256/f9e6ad1a4ed7ae2bf8ca5de5a0ef7ba2e9a1cc4d0c5a9b3e7a0d85b3b0a0f0a1",
+                "http://localhost/debian/dists/bookworm/main/binary-amd64/Release",
+            ]
+        );
+        assert_eq!(files[1].relative, vec!["dists", "bookworm", "contrib"]);
+        assert_eq!(files[1].filename, "Contents-all.gz");
+        assert_eq!(files[1].size, 98581);
+        assert_eq!(
+            files[1].checksum,
+            Some(Checksum::Sha256(
+                "3e9a121d599b56c08bc8f144e4830807c77c29d7114316d6984ba54695d3db7b".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_sources() {
+        let data = "Package: acl
+Binary: acl, libacl1, libacl1-dev
+Directory: pool/main/a/acl
+Files:
+ 5c6d4e4e9b3f0b5f8e2a1c0d9e8f7a6b 2380 acl_2.3.1-3.dsc
+Checksums-Sha256:
+ 8d7a5f7c1d4d3f9a9a0e1f2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f 2380 acl_2.3.1-3.dsc
+ 760c61c68901b37fdd5eefeeaf4c0c7a26bdfdd8ac747a1edff1ce0e243c11af 355676 acl_2.3.1.orig.tar.xz
+
+Package: adduser
+directory: pool/main/a/adduser/
+Files:
+ 7e4a2e0c3b8a0d4f5e6c7b8a9d0e1f2a 1670 adduser_3.134.dsc
+";
+        let url = Url::parse("http://localhost/debian/").unwrap();
+        let files = parse_sources(data, &[], &url).unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(
+            files[1].url.as_str(),
+            "http://localhost/debian/pool/main/a/acl/acl_2.3.1.orig.tar.xz"
+        );
+        assert_eq!(files[1].relative, vec!["pool", "main", "a", "acl"]);
+        assert_eq!(files[1].size, 355676);
+        assert!(files[1].checksum.is_some());
+        // Files (md5) is used when no better checksum is provided
+        assert_eq!(files[2].filename, "adduser_3.134.dsc");
+        assert_eq!(
+            files[2].checksum,
+            Some(Checksum::Md5(
+                "7e4a2e0c3b8a0d4f5e6c7b8a9d0e1f2a".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_flat_repo() {
+        let data = "Package: proxmox-backup-client
+Version: 3.1.2-1
+Architecture: amd64
+Maintainer: Proxmox Support Team <support@proxmox.com>
+Description: Proxmox Backup Client tools
+Filename: ./proxmox-backup-client_3.1.2-1_amd64.deb
+Size: 3162728
+SHA256: 1f5ab1f0b64d3c7ab8d6e8f79d3cbd6a1a6b8b2f8d9c0e1f2a3b4c5d6e7f8091
+
+Package: proxmox-backup-docs
+Version: 3.1.2-1
+Architecture: all
+Maintainer: Proxmox Support Team <support@proxmox.com>
+Description: Proxmox Backup Documentation
+Filename: docs/proxmox-backup-docs_3.1.2-1_all.deb
+Size: 4271236
+";
+        let index_path = Path::new("/var/www/html/vendor/flat/Packages");
+        assert!(matches!(
+            get_apt_index_type(index_path),
+            Some(AptIndexType::Packages)
+        ));
+        let relative = vec!["vendor".to_string(), "flat".to_string()];
+        let url = Url::parse("http://localhost/vendor/flat/Packages").unwrap();
+        let (root_relative, root_url) = get_apt_root(index_path, &relative, &url).unwrap();
+        assert_eq!(root_relative, relative);
+        assert_eq!(
+            root_url,
+            Url::parse("http://localhost/vendor/flat/").unwrap()
+        );
+        let mut packages = parse_packages(data, &root_relative, &root_url).unwrap();
+        // apt-parser parses in parallel, thus order is not kept
+        packages.sort_by(|a, b| a.filename.cmp(&b.filename));
+        assert_eq!(packages.len(), 2);
+        assert_eq!(
+            packages[0].url.as_str(),
+            "http://localhost/vendor/flat/proxmox-backup-client_3.1.2-1_amd64.deb"
+        );
+        assert_eq!(packages[0].relative, vec!["vendor", "flat"]);
+        assert_eq!(
+            packages[0].filename,
+            "proxmox-backup-client_3.1.2-1_amd64.deb"
+        );
+        assert_eq!(packages[1].relative, vec!["vendor", "flat", "docs"]);
+
+        // Release at flat root lists index files next to it
+        let release = "Origin: Vendor
+SHA256:
+ 5b8a2a5b4e2c1d0f9e8d7c6b5a4938271605f4e3d2c1b0a9f8e7d6c5b4a39281 1024 Packages
+ 0a9f8e7d6c5b4a39281605f4e3d2c1b05b8a2a5b4e2c1d0f9e8d7c6b5a493827 512 Packages.gz
+";
+        let release_url = Url::parse("http://localhost/vendor/flat/Release").unwrap();
+        let files = parse_release(release, &relative, &release_url).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].url.as_str(),
+            "http://localhost/vendor/flat/Packages.gz"
+        );
+        assert_eq!(files[0].relative, vec!["vendor", "flat"]);
+    }
+
+    #[test]
+    fn test_sources_index() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/apt/debian/dists/bookworm/main/source/Sources.xz");
+        assert!(matches!(
+            get_apt_index_type(&path),
+            Some(AptIndexType::Sources)
+        ));
+        let relative: Vec<String> = ["debian", "dists", "bookworm", "main", "source"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let url =
+            Url::parse("http://localhost/debian/dists/bookworm/main/source/Sources.xz").unwrap();
+        let files = parse_index(&path, &relative, &url, AptIndexType::Sources).unwrap();
+        assert_eq!(files.len(), 6);
+        assert_eq!(
+            files[3].url.as_str(),
+            "http://localhost/debian/pool/main/a/acl/acl_2.3.1-3.debian.tar.xz"
+        );
+        assert_eq!(
+            files[3].relative,
+            vec!["debian", "pool", "main", "a", "acl"]
+        );
+        assert_eq!(files[3].filename, "acl_2.3.1-3.debian.tar.xz");
+        assert_eq!(files[3].size, 26536);
+        assert_eq!(
+            files[3].checksum,
+            Some(Checksum::Sha256(
+                "5d4c3b2a19f8e7d6c5b4a39281706f5e4d3c2b1a0f9e8d7c6b5a493827160f5e".to_string()
+            ))
+        );
+        assert_eq!(
+            files[5].relative,
+            vec!["debian", "pool", "main", "a", "adduser"]
+        );
+    }
+
     #[test]
     fn test_debian_root() {
         let packages_path = Path::new("/var/www/html/dists/buster/main/binary-amd64/Packages");
@@ -176,4 +624,20 @@ mod tests {
             Url::parse("http://repo.mysql.com/apt/ubuntu/").unwrap()
         );
     }
+
+    #[test]
+    fn test_package_root() {
+        assert_eq!(
+            AptExtension.package_root(Path::new(
+                "/srv/mysql/apt/ubuntu/dists/jammy/mysql-8.0/binary-amd64"
+            )),
+            Path::new("/srv/mysql/apt/ubuntu")
+        );
+        assert_eq!(
+            AptExtension.package_root(Path::new("/srv/flat")),
+            Path::new("/srv/flat")
+        );
+        assert!(AptExtension.is_package("debian/pool/main/a/acl/acl_2.3.1-3_amd64.deb"));
+        assert!(!AptExtension.is_package("debian/dists/bookworm/InRelease"));
+    }
 }
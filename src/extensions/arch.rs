@@ -0,0 +1,206 @@
+// Arch Linux (pacman) repos: <repo>.db and <repo>.files in each <repo>/os/<arch>/ list packages next to them.
+// They are tarballs with a <name>-<version>/desc entry per package, and <repo>.db is usually a symlink
+// to <repo>.db.tar.gz (or .tar.zst, .tar.xz), so compression is detected by magic bytes.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+
+use anyhow::{anyhow, Result};
+use bzip2::read::BzDecoder;
+use flate2::read::MultiGzDecoder;
+use url::Url;
+use xz2::read::XzDecoder;
+
+use super::ExtensionPackage;
+use crate::checksum::Checksum;
+
+const DATABASES: &[&str] = &[".db", ".files"];
+const COMPRESSIONS: &[&str] = &["", ".tar", ".tar.gz", ".tar.xz", ".tar.zst", ".tar.bz2"];
+
+#[derive(Debug, PartialEq)]
+struct DescEntry {
+    filename: String,
+    size: Option<u64>,
+    sha256: Option<String>,
+}
+
+fn is_pacman_db(p: &Path) -> bool {
+    let Some(name) = p.file_name().and_then(|f| f.to_str()) else {
+        return false;
+    };
+    DATABASES.iter().any(|db| {
+        COMPRESSIONS.iter().any(|c| {
+            name.strip_suffix(c)
+                .and_then(|n| n.strip_suffix(db))
+                .is_some_and(|n| !n.is_empty())
+        })
+    })
+}
+
+fn open_database(p: &Path) -> Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(File::open(p)?);
+    let magic = reader.fill_buf()?;
+    Ok(if magic.starts_with(&[0x1f, 0x8b]) {
+        Box::new(MultiGzDecoder::new(reader))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Box::new(zstd::Decoder::with_buffer(reader)?)
+    } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        Box::new(XzDecoder::new(reader))
+    } else if magic.starts_with(b"BZh") {
+        Box::new(BzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    })
+}
+
+fn read_descs(p: &Path) -> Result<Vec<String>> {
+    let mut archive = tar::Archive::new(open_database(p)?);
+    let mut res = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.file_name().is_some_and(|f| f == "desc") {
+            let mut s = String::new();
+            entry.read_to_string(&mut s)?;
+            res.push(s);
+        }
+    }
+    Ok(res)
+}
+
+// Fields are separated by empty lines, each with a "%NAME%" line followed by its values.
+fn parse_desc(data: &str) -> Option<DescEntry> {
+    let (mut filename, mut size, mut sha256) = (None, None, None);
+    for block in data.split("\n\n") {
+        let mut lines = block.lines().skip_while(|l| l.is_empty());
+        match (lines.next(), lines.next()) {
+            (Some("%FILENAME%"), Some(v)) => filename = Some(v.to_string()),
+            (Some("%CSIZE%"), Some(v)) => size = v.parse().ok(),
+            (Some("%SHA256SUM%"), Some(v)) => sha256 = Some(v.to_string()),
+            _ => {}
+        }
+    }
+    Some(DescEntry {
+        filename: filename?,
+        size,
+        sha256,
+    })
+}
+
+fn sibling_url(url: &Url, filename: &str) -> Result<Url> {
+    let mut url = url.clone();
+    url.path_segments_mut()
+        .map_err(|_| anyhow!("URL cannot be a base"))?
+        .pop()
+        .push(filename);
+    Ok(url)
+}
+
+pub struct ArchExtension;
+
+impl super::RepoExtension for ArchExtension {
+    fn name(&self) -> &'static str {
+        "Arch"
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        is_pacman_db(path)
+    }
+
+    fn parse(&self, path: &Path, relative: &[String], url: &Url) -> Result<Vec<ExtensionPackage>> {
+        let mut res = vec![];
+        for desc in read_descs(path)? {
+            let Some(entry) = parse_desc(&desc) else {
+                continue;
+            };
+            let signature = format!("{}.sig", entry.filename);
+            res.push(ExtensionPackage {
+                url: sibling_url(url, &entry.filename)?,
+                relative: relative.to_vec(),
+                filename: entry.filename,
+                size: entry.size,
+                checksum: entry.sha256.and_then(|d| Checksum::new("sha256", &d)),
+                optional: false,
+            });
+            res.push(ExtensionPackage {
+                url: sibling_url(url, &signature)?,
+                relative: relative.to_vec(),
+                filename: signature,
+                size: None,
+                checksum: None,
+                optional: true,
+            });
+        }
+        Ok(res)
+    }
+
+    fn is_metadata(&self, relative: &str) -> bool {
+        is_pacman_db(Path::new(relative))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::extensions::RepoExtension;
+
+    #[test]
+    fn test_pacman_db() {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/arch/core/os/x86_64/core.db");
+        assert!(ArchExtension.matches(&path));
+        for name in ["core.files", "extra.db.tar.gz", "extra.files.tar.zst"] {
+            assert!(ArchExtension.is_metadata(&format!("extra/os/x86_64/{name}")));
+        }
+        for name in [".db", "core.db.sig", "acl-2.3.2-1-x86_64.pkg.tar.zst"] {
+            assert!(!ArchExtension.is_metadata(&format!("core/os/x86_64/{name}")));
+        }
+
+        let mut entries: Vec<DescEntry> = read_descs(&path)
+            .unwrap()
+            .iter()
+            .filter_map(|d| parse_desc(d))
+            .collect();
+        entries.sort_by(|a, b| a.filename.cmp(&b.filename));
+        assert_eq!(
+            entries[0],
+            DescEntry {
+                filename: "acl-2.3.2-1-x86_64.pkg.tar.zst".to_string(),
+                size: Some(140731),
+                sha256: Some(
+                    "6a2c6a0e3e4f0f8c1d3b7a9e5c4d2b1a0f9e8d7c6b5a4938271605f4e3d2c1b0".to_string()
+                ),
+            }
+        );
+
+        let relative: Vec<String> = ["core", "os", "x86_64"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let url = Url::parse("http://localhost/archlinux/core/os/x86_64/core.db").unwrap();
+        let packages = ArchExtension.parse(&path, &relative, &url).unwrap();
+        assert_eq!(packages.len(), 4);
+        let zlib = packages
+            .iter()
+            .find(|p| p.filename == "zlib-1:1.3.1-2-x86_64.pkg.tar.zst")
+            .unwrap();
+        assert_eq!(
+            zlib.url.as_str(),
+            "http://localhost/archlinux/core/os/x86_64/zlib-1:1.3.1-2-x86_64.pkg.tar.zst"
+        );
+        assert_eq!(zlib.relative, relative);
+        assert_eq!(zlib.size, Some(92180));
+        assert!(zlib.checksum.is_some());
+        let signature = packages
+            .iter()
+            .find(|p| p.filename == "zlib-1:1.3.1-2-x86_64.pkg.tar.zst.sig")
+            .unwrap();
+        assert_eq!(signature.size, None);
+        assert!(signature.checksum.is_none());
+        assert!(signature.optional);
+    }
+}
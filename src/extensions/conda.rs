@@ -0,0 +1,125 @@
+// Conda channels: <subdir>/repodata.json lists packages (.tar.bz2 and .conda) next to it.
+
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use url::Url;
+
+use super::{read_compressed, ExtensionPackage};
+use crate::checksum::Checksum;
+
+const REPODATA_FILES: &[&str] = &["repodata.json", "current_repodata.json"];
+const PACKAGE_SUFFIXES: &[&str] = &[".tar.bz2", ".conda"];
+
+#[derive(Debug, Deserialize)]
+struct PackageRecord {
+    size: Option<u64>,
+    sha256: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepoData {
+    #[serde(default)]
+    packages: BTreeMap<String, PackageRecord>,
+    #[serde(default, rename = "packages.conda")]
+    packages_conda: BTreeMap<String, PackageRecord>,
+}
+
+/// repodata.json, current_repodata.json and their compressed variants
+fn is_repodata(p: &Path) -> bool {
+    p.file_name().and_then(|f| f.to_str()).is_some_and(|f| {
+        let f = ["bz2", "zst", "gz"]
+            .iter()
+            .find_map(|ext| f.strip_suffix(&format!(".{ext}")))
+            .unwrap_or(f);
+        REPODATA_FILES.contains(&f)
+    })
+}
+
+fn read_repodata(p: &Path) -> Result<RepoData> {
+    Ok(serde_json::from_str(&read_compressed(p)?)?)
+}
+
+pub struct CondaExtension;
+
+impl super::RepoExtension for CondaExtension {
+    fn name(&self) -> &'static str {
+        "Conda"
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        is_repodata(path)
+    }
+
+    fn parse(&self, path: &Path, relative: &[String], url: &Url) -> Result<Vec<ExtensionPackage>> {
+        let repodata = read_repodata(path)?;
+        let mut res = vec![];
+        for (filename, record) in repodata.packages.into_iter().chain(repodata.packages_conda) {
+            let mut url = url.clone();
+            url.path_segments_mut()
+                .map_err(|_| anyhow!("URL cannot be a base"))?
+                .pop()
+                .push(&filename);
+            res.push(ExtensionPackage {
+                url,
+                relative: relative.to_vec(),
+                filename,
+                size: record.size,
+                checksum: record.sha256.and_then(|d| Checksum::new("sha256", &d)),
+                optional: false,
+            });
+        }
+        Ok(res)
+    }
+
+    fn is_metadata(&self, relative: &str) -> bool {
+        is_repodata(Path::new(relative))
+    }
+
+    fn is_package(&self, relative: &str) -> bool {
+        PACKAGE_SUFFIXES.iter().any(|s| relative.ends_with(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::extensions::RepoExtension;
+
+    #[test]
+    fn test_repodata() {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/conda/linux-64/repodata.json");
+        assert!(CondaExtension.matches(&path));
+        assert!(CondaExtension.is_metadata("main/noarch/repodata.json.zst"));
+        assert!(CondaExtension.is_metadata("main/linux-64/current_repodata.json.bz2"));
+        assert!(!CondaExtension.is_metadata("main/channeldata.json"));
+        assert!(CondaExtension.is_package("main/linux-64/zlib-1.2.13-h5eee18b_1.conda"));
+        assert!(!CondaExtension.is_package("main/linux-64/repodata.json"));
+
+        let relative = vec!["main".to_string(), "linux-64".to_string()];
+        let url = Url::parse("http://localhost/conda/main/linux-64/repodata.json").unwrap();
+        let packages = CondaExtension.parse(&path, &relative, &url).unwrap();
+        let filenames: Vec<&str> = packages.iter().map(|p| p.filename.as_str()).collect();
+        assert_eq!(
+            filenames,
+            vec![
+                "zlib-1.2.13-h5eee18b_0.tar.bz2",
+                "broken-0.1-0.conda",
+                "zlib-1.2.13-h5eee18b_1.conda",
+            ]
+        );
+        assert_eq!(
+            packages[2].url.as_str(),
+            "http://localhost/conda/main/linux-64/zlib-1.2.13-h5eee18b_1.conda"
+        );
+        assert_eq!(packages[2].relative, relative);
+        assert_eq!(packages[2].size, Some(113316));
+        assert!(packages[2].checksum.is_some());
+        assert_eq!(packages[1].size, None);
+        assert!(packages[1].checksum.is_none());
+    }
+}
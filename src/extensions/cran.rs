@@ -0,0 +1,171 @@
+// CRAN-like R repos: PACKAGES in src/contrib and bin/<platform>/contrib/<version> lists packages next to it.
+// Entries are in the same format as APT Packages (Debian control files).
+
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use tracing::warn;
+use url::Url;
+
+use super::{
+    apt::{parse_paragraphs, Paragraph},
+    read_compressed, ExtensionPackage,
+};
+use crate::checksum::Checksum;
+
+fn is_in_contrib(p: &Path) -> bool {
+    p.components().any(|c| c.as_os_str() == "contrib")
+}
+
+fn is_cran_packages(p: &Path) -> bool {
+    is_in_contrib(p)
+        && p.file_name()
+            .is_some_and(|f| f == "PACKAGES" || f == "PACKAGES.gz")
+}
+
+/// File extension of packages, by the platform in URL (bin/windows, bin/macosx, or src)
+fn package_suffix(url: &Url) -> &'static str {
+    let mut segments = url.path_segments().into_iter().flatten();
+    match segments.find(|s| *s == "bin").and_then(|_| segments.next()) {
+        Some("windows") => "zip",
+        Some("macosx") => "tgz",
+        _ => "tar.gz",
+    }
+}
+
+fn make_package(
+    paragraph: &Paragraph,
+    url: &Url,
+    relative: &[String],
+    suffix: &str,
+) -> Option<ExtensionPackage> {
+    let filename = match paragraph.get("File") {
+        Some(file) => file.to_string(),
+        None => format!(
+            "{}_{}.{}",
+            paragraph.get("Package")?,
+            paragraph.get("Version")?,
+            suffix
+        ),
+    };
+    // Path is relative to PACKAGES, like "4.3.3/Recommended"
+    let mut relative = relative.to_vec();
+    let mut url = url.clone();
+    {
+        let mut segments = url.path_segments_mut().ok()?;
+        segments.pop();
+        for segment in paragraph
+            .get("Path")
+            .into_iter()
+            .flat_map(|p| p.split('/'))
+            .filter(|s| !s.is_empty() && *s != ".")
+        {
+            segments.push(segment);
+            relative.push(segment.to_string());
+        }
+        segments.push(&filename);
+    }
+    Some(ExtensionPackage {
+        url,
+        relative,
+        filename,
+        size: None,
+        checksum: paragraph
+            .get("MD5sum")
+            .and_then(|d| Checksum::new("md5", d)),
+        optional: false,
+    })
+}
+
+fn parse_packages(path: &Path, relative: &[String], url: &Url) -> Result<Vec<ExtensionPackage>> {
+    let data = read_compressed(path)?;
+    let suffix = package_suffix(url);
+    let mut res = vec![];
+    for paragraph in parse_paragraphs(&data) {
+        match make_package(&paragraph, url, relative, suffix) {
+            Some(package) => res.push(package),
+            None => warn!("Invalid entry in {:?}: {:?}", path, paragraph),
+        }
+    }
+    if res.is_empty() && !data.trim().is_empty() {
+        return Err(anyhow!("No package found in {:?}", path));
+    }
+    Ok(res)
+}
+
+pub struct CranExtension;
+
+impl super::RepoExtension for CranExtension {
+    fn name(&self) -> &'static str {
+        "CRAN"
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        is_cran_packages(path)
+    }
+
+    fn parse(&self, path: &Path, relative: &[String], url: &Url) -> Result<Vec<ExtensionPackage>> {
+        parse_packages(path, relative, url)
+    }
+
+    fn is_metadata(&self, relative: &str) -> bool {
+        let p = Path::new(relative);
+        is_in_contrib(p)
+            && p.file_name()
+                .and_then(|f| f.to_str())
+                .is_some_and(|f| f.starts_with("PACKAGES"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::extensions::RepoExtension;
+
+    #[test]
+    fn test_cran_packages() {
+        let path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/cran/src/contrib/PACKAGES");
+        assert!(CranExtension.matches(&path));
+        assert!(!CranExtension.matches(Path::new(
+            "debian/dists/bookworm/main/binary-amd64/Packages"
+        )));
+        assert!(CranExtension.is_metadata("src/contrib/PACKAGES.rds"));
+        assert!(CranExtension.is_metadata("bin/windows/contrib/4.3/PACKAGES.gz"));
+        assert!(!CranExtension.is_metadata("src/contrib/abc_2.2.1.tar.gz"));
+
+        let relative = vec!["src".to_string(), "contrib".to_string()];
+        let url = Url::parse("https://cran.example.com/src/contrib/PACKAGES").unwrap();
+        let packages = CranExtension.parse(&path, &relative, &url).unwrap();
+        assert_eq!(packages.len(), 3);
+        assert_eq!(packages[1].filename, "abc_2.2.1.tar.gz");
+        assert_eq!(
+            packages[1].url.as_str(),
+            "https://cran.example.com/src/contrib/abc_2.2.1.tar.gz"
+        );
+        assert_eq!(packages[1].relative, relative);
+        assert_eq!(
+            packages[1].checksum,
+            Checksum::new("md5", "c9fffe4334c178917f762735aba59653")
+        );
+        assert_eq!(
+            packages[2].url.as_str(),
+            "https://cran.example.com/src/contrib/4.3.3/Recommended/survival_3.5-8.tar.gz"
+        );
+        assert_eq!(
+            packages[2].relative,
+            vec!["src", "contrib", "4.3.3", "Recommended"]
+        );
+
+        let url = Url::parse("https://cran.example.com/bin/windows/contrib/4.3/PACKAGES").unwrap();
+        let packages = CranExtension.parse(&path, &relative, &url).unwrap();
+        assert_eq!(packages[0].filename, "A3_1.0.0.zip");
+        let url =
+            Url::parse("https://cran.example.com/bin/macosx/big-sur-arm64/contrib/4.3/PACKAGES")
+                .unwrap();
+        let packages = CranExtension.parse(&path, &relative, &url).unwrap();
+        assert_eq!(packages[0].filename, "A3_1.0.0.tgz");
+    }
+}
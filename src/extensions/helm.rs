@@ -0,0 +1,311 @@
+// Helm chart repos: index.yaml lists chart tarballs in entries.*[].urls, relative to it or absolute
+// (possibly on another host, then put in <host>/<path> next to it). Absolute URLs are rewritten in local index.yaml to relative ones,
+// so that charts are fetched from the mirror. Upstream index.yaml is kept as is to compare with.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{anyhow, Result};
+use serde_yaml::Value;
+use url::Url;
+
+use super::ExtensionPackage;
+use crate::checksum::Checksum;
+
+fn is_helm_index(p: &Path) -> bool {
+    p.file_name().is_some_and(|f| f == "index.yaml")
+}
+
+fn decode(s: &str) -> String {
+    percent_encoding::percent_decode_str(s)
+        .decode_utf8_lossy()
+        .to_string()
+}
+
+/// URL of chart, and its (percent-encoded) path relative to index dir.
+/// Charts outside index dir are put in <host>/<path> next to index.yaml.
+fn chart_location(dir: &Url, href: &str) -> Result<(Url, String)> {
+    let url = dir.join(href)?;
+    let relative = match url.as_str().strip_prefix(dir.as_str()) {
+        Some(rest) if url.query().is_none() && url.fragment().is_none() => rest.to_string(),
+        _ => match url.host_str() {
+            Some(host) => format!("{host}{}", url.path()),
+            None => return Err(anyhow!("Invalid chart URL: {:?}", href)),
+        },
+    };
+    if relative.is_empty() || relative.ends_with('/') {
+        return Err(anyhow!("Invalid chart URL: {:?}", href));
+    }
+    Ok((url, relative))
+}
+
+fn packages_of_chart(
+    url: &Url,
+    relative: &[String],
+    chart_relative: &str,
+    digest: Option<&str>,
+) -> Vec<ExtensionPackage> {
+    let mut segments: Vec<String> = chart_relative.split('/').map(decode).collect();
+    let filename = segments.pop().unwrap_or_default();
+    let mut relative = relative.to_vec();
+    relative.append(&mut segments);
+    let mut prov_url = url.clone();
+    prov_url.set_path(&format!("{}.prov", url.path()));
+    vec![
+        ExtensionPackage {
+            url: url.clone(),
+            relative: relative.clone(),
+            filename: filename.clone(),
+            size: None,
+            checksum: digest.and_then(|d| Checksum::new("sha256", d)),
+            optional: false,
+        },
+        // Provenance files exist only for signed charts
+        ExtensionPackage {
+            url: prov_url,
+            relative,
+            filename: format!("{filename}.prov"),
+            size: None,
+            checksum: None,
+            optional: true,
+        },
+    ]
+}
+
+/// Find out charts in index, rewriting their absolute URLs to relative ones.
+/// Returns whether index is changed.
+fn parse_index(
+    index: &mut Value,
+    relative: &[String],
+    url: &Url,
+    res: &mut Vec<ExtensionPackage>,
+) -> Result<bool> {
+    let dir = url.join(".")?;
+    let entries = index
+        .get_mut("entries")
+        .and_then(|e| e.as_mapping_mut())
+        .ok_or_else(|| anyhow!("No entries in index"))?;
+    let mut changed = false;
+    // Charts put at the same local path (like those differ only in port or query)
+    let mut locations: HashMap<String, Url> = HashMap::new();
+    for versions in entries.values_mut().filter_map(|v| v.as_sequence_mut()) {
+        for version in versions {
+            let digest = version
+                .get("digest")
+                .and_then(|d| d.as_str())
+                .map(|d| d.to_string());
+            let Some(urls) = version.get_mut("urls").and_then(|u| u.as_sequence_mut()) else {
+                continue;
+            };
+            for href in urls {
+                let Some(s) = href.as_str() else {
+                    continue;
+                };
+                let (chart_url, chart_relative) = chart_location(&dir, s)?;
+                match locations.get(&chart_relative) {
+                    Some(other) if *other != chart_url => {
+                        return Err(anyhow!(
+                            "Charts {} and {} are both put at {}",
+                            other,
+                            chart_url,
+                            chart_relative
+                        ));
+                    }
+                    _ => {
+                        locations.insert(chart_relative.clone(), chart_url.clone());
+                    }
+                }
+                res.append(&mut packages_of_chart(
+                    &chart_url,
+                    relative,
+                    &chart_relative,
+                    digest.as_deref(),
+                ));
+                if Url::parse(s).is_ok() {
+                    *href = Value::String(chart_relative);
+                    changed = true;
+                }
+            }
+        }
+    }
+    Ok(changed)
+}
+
+pub struct HelmExtension;
+
+impl super::RepoExtension for HelmExtension {
+    fn name(&self) -> &'static str {
+        "Helm"
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        is_helm_index(path)
+    }
+
+    fn parse(&self, path: &Path, relative: &[String], url: &Url) -> Result<Vec<ExtensionPackage>> {
+        let mut index: Value = serde_yaml::from_str(&std::fs::read_to_string(path)?)?;
+        let mut res = vec![];
+        parse_index(&mut index, relative, url, &mut res)?;
+        Ok(res)
+    }
+
+    fn is_metadata(&self, relative: &str) -> bool {
+        is_helm_index(Path::new(relative))
+    }
+
+    fn is_rewritten(&self, relative: &str) -> bool {
+        is_helm_index(Path::new(relative))
+    }
+
+    fn rewrite(&self, path: &Path, url: &Url) -> Result<String> {
+        let content = std::fs::read_to_string(path)?;
+        let mut index: Value = serde_yaml::from_str(&content)?;
+        if parse_index(&mut index, &[], url, &mut vec![])? {
+            Ok(serde_yaml::to_string(&index)?)
+        } else {
+            Ok(content)
+        }
+    }
+
+    fn is_package(&self, relative: &str) -> bool {
+        relative.ends_with(".tgz") || relative.ends_with(".tgz.prov")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::extensions::RepoExtension;
+
+    #[test]
+    fn test_helm_index() {
+        let fixture =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/helm/stable/index.yaml");
+        let dir = std::env::temp_dir().join(format!("tsumugu-helm-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.yaml");
+        std::fs::copy(&fixture, &path).unwrap();
+        assert!(HelmExtension.matches(&path));
+
+        let relative = vec!["stable".to_string()];
+        let url = Url::parse("https://charts.example.com/stable/index.yaml").unwrap();
+        let packages = HelmExtension.parse(&path, &relative, &url).unwrap();
+        let locations: Vec<(String, String, bool)> = packages
+            .iter()
+            .map(|p| {
+                (
+                    p.url.to_string(),
+                    format!("{}/{}", p.relative.join("/"), p.filename),
+                    p.optional,
+                )
+            })
+            .collect();
+        assert_eq!(
+            locations[..4],
+            [
+                (
+                    "https://charts.example.com/stable/charts/nginx-15.14.0.tgz".to_string(),
+                    "stable/charts/nginx-15.14.0.tgz".to_string(),
+                    false
+                ),
+                (
+                    "https://charts.example.com/stable/charts/nginx-15.14.0.tgz.prov".to_string(),
+                    "stable/charts/nginx-15.14.0.tgz.prov".to_string(),
+                    true
+                ),
+                (
+                    "https://charts.example.com/stable/charts/nginx-15.13.0.tgz".to_string(),
+                    "stable/charts/nginx-15.13.0.tgz".to_string(),
+                    false
+                ),
+                (
+                    "https://charts.example.com/stable/charts/nginx-15.13.0.tgz.prov".to_string(),
+                    "stable/charts/nginx-15.13.0.tgz.prov".to_string(),
+                    true
+                ),
+            ]
+        );
+        assert_eq!(
+            locations[4].0,
+            "https://github.com/example/charts/releases/download/redis-18.19.0/redis-18.19.0.tgz"
+        );
+        assert_eq!(
+            locations[4].1,
+            "stable/github.com/example/charts/releases/download/redis-18.19.0/redis-18.19.0.tgz"
+        );
+        assert!(packages[0].checksum.is_some());
+
+        // Absolute URLs are rewritten for local index.yaml, leaving upstream one untouched
+        assert!(HelmExtension.is_rewritten("stable/index.yaml"));
+        let rewritten = HelmExtension.rewrite(&path, &url).unwrap();
+        let rewritten: Value = serde_yaml::from_str(&rewritten).unwrap();
+        let urls: Vec<&str> = rewritten["entries"]
+            .as_mapping()
+            .unwrap()
+            .values()
+            .flat_map(|v| v.as_sequence().unwrap())
+            .flat_map(|v| v["urls"].as_sequence().unwrap())
+            .map(|u| u.as_str().unwrap())
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "charts/nginx-15.14.0.tgz",
+                "charts/nginx-15.13.0.tgz",
+                "github.com/example/charts/releases/download/redis-18.19.0/redis-18.19.0.tgz"
+            ]
+        );
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            std::fs::read(&fixture).unwrap()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_helm_same_basename() {
+        let dir = std::env::temp_dir().join(format!("tsumugu-helm-same-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.yaml");
+        let url = Url::parse("https://charts.example.com/index.yaml").unwrap();
+        let index = |urls: [&str; 2]| {
+            format!(
+                "apiVersion: v1\nentries:\n  app:\n  - name: app\n    urls:\n    - {}\n    version: 1.0.0\n  - name: app\n    urls:\n    - {}\n    version: 1.0.0\n",
+                urls[0], urls[1]
+            )
+        };
+
+        // Charts on different hosts with the same file name are kept apart
+        std::fs::write(
+            &path,
+            index([
+                "https://a.example.com/app-1.0.0.tgz",
+                "https://b.example.com/app-1.0.0.tgz",
+            ]),
+        )
+        .unwrap();
+        let packages = HelmExtension.parse(&path, &[], &url).unwrap();
+        let locations: Vec<String> = packages
+            .iter()
+            .filter(|p| !p.optional)
+            .map(|p| format!("{}/{}", p.relative.join("/"), p.filename))
+            .collect();
+        assert_eq!(
+            locations,
+            ["a.example.com/app-1.0.0.tgz", "b.example.com/app-1.0.0.tgz"]
+        );
+
+        // Those still put at the same path are rejected
+        std::fs::write(
+            &path,
+            index([
+                "https://a.example.com/app-1.0.0.tgz",
+                "https://a.example.com:8443/app-1.0.0.tgz",
+            ]),
+        )
+        .unwrap();
+        assert!(HelmExtension.parse(&path, &[], &url).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
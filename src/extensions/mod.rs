@@ -1,65 +1,171 @@
-use crate::SyncArgs;
-use std::path::Path;
+use crate::checksum::Checksum;
+use anyhow::Result;
+use bzip2::read::BzDecoder;
+use clap::ValueEnum;
+use flate2::read::GzDecoder;
+use std::{fs::File, io::Read, path::Path};
 use tracing::{info, warn};
 use url::Url;
+use xz2::read::XzDecoder;
 
+mod alpine;
 mod apt;
+mod arch;
+mod conda;
+mod cran;
+mod helm;
+pub mod nix;
 mod yum;
 
+/// Directory (in download dir) keeping upstream copies of files rewritten by extensions.
+pub const UPSTREAM_DIR: &str = ".tsumugu-upstream";
+
+/// Extension for a kind of package repository, finding out files referred by its metadata.
+pub trait RepoExtension: Sync {
+    fn name(&self) -> &'static str;
+    /// Whether the downloaded file is metadata to parse.
+    fn matches(&self, path: &Path) -> bool;
+    /// Files referred by the metadata.
+    /// `relative` is the directory of metadata, relative to download dir, and `url` is its URL.
+    fn parse(&self, path: &Path, relative: &[String], url: &Url) -> Result<Vec<ExtensionPackage>>;
+    /// Whether the file (relative to download dir) is metadata, to be staged in metadata-last sync.
+    fn is_metadata(&self, relative: &str) -> bool;
+    /// Whether the file (relative to download dir) is a package referred by metadata,
+    /// to be deleted with --delete-unreferenced when parsed metadata no longer refers to it.
+    fn is_package(&self, _relative: &str) -> bool {
+        false
+    }
+    /// Directory whose packages are all referred by metadata in `dir`, like repo root of APT dists.
+    fn package_root<'a>(&self, dir: &'a Path) -> &'a Path {
+        dir
+    }
+    /// Whether the file (relative to download dir) is served rewritten, like Helm index.yaml.
+    /// Its upstream copy is then synced into UPSTREAM_DIR instead.
+    fn is_rewritten(&self, _relative: &str) -> bool {
+        false
+    }
+    /// Content of local file rewritten from the upstream one at `path`, whose URL is `url`.
+    fn rewrite(&self, path: &Path, _url: &Url) -> Result<String> {
+        Ok(std::fs::read_to_string(path)?)
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ExtensionType {
+    /// APT repos: InRelease/Release, Packages and Sources
+    Apt,
+    /// YUM repos: repomd.xml and primary.xml
+    Yum,
+    /// Alpine repos: APKINDEX.tar.gz
+    Alpine,
+    /// Arch Linux (pacman) repos: <repo>.db and <repo>.files
+    Arch,
+    /// Conda channels: repodata.json
+    Conda,
+    /// Helm chart repos: index.yaml
+    Helm,
+    /// CRAN-like R repos: PACKAGES in contrib directories
+    Cran,
+    /// Nix binary caches: <hash>.narinfo
+    Nix,
+}
+
+impl ExtensionType {
+    pub fn build(&self) -> Box<dyn RepoExtension> {
+        match self {
+            Self::Apt => Box::new(apt::AptExtension),
+            Self::Yum => Box::new(yum::YumExtension),
+            Self::Alpine => Box::new(alpine::AlpineExtension),
+            Self::Arch => Box::new(arch::ArchExtension),
+            Self::Conda => Box::new(conda::CondaExtension),
+            Self::Helm => Box::new(helm::HelmExtension),
+            Self::Cran => Box::new(cran::CranExtension),
+            Self::Nix => Box::new(nix::NixExtension),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct ExtensionPackage {
     pub url: Url,
     pub relative: Vec<String>,
     pub filename: String,
+    pub size: Option<u64>,
+    pub checksum: Option<Checksum>,
+    /// The file might not exist, like signatures which are not always provided.
+    pub optional: bool,
+}
+
+/// Read metadata file, decompressing it according to extension.
+fn read_compressed(p: &Path) -> Result<String> {
+    let file = File::open(p)?;
+    let mut reader: Box<dyn Read> = match p.extension().and_then(|e| e.to_str()) {
+        Some("gz") => Box::new(GzDecoder::new(file)),
+        Some("xz") => Box::new(XzDecoder::new(file)),
+        Some("bz2") => Box::new(BzDecoder::new(file)),
+        Some("zst") => Box::new(zstd::Decoder::new(file)?),
+        Some(ext @ ("lzma" | "zck")) => {
+            return Err(anyhow::anyhow!("Unsupported compression: {}", ext))
+        }
+        _ => Box::new(file),
+    };
+    let mut s = String::new();
+    reader.read_to_string(&mut s)?;
+    Ok(s)
 }
 
+/// Parse metadata with matching extensions. Returns None if no extension matches,
+/// otherwise whether all of them succeeded.
 pub fn extension_handler<F>(
-    args: &SyncArgs,
+    extensions: &[Box<dyn RepoExtension>],
     path: &Path,
     relative: &[String],
     url: &Url,
     push_func: F,
-) where
+) -> Option<bool>
+where
     F: Fn(&ExtensionPackage),
 {
-    if args.apt_packages && crate::extensions::apt::is_apt_package(path) {
-        let packages = apt::parse_package(path, relative, url);
-        match packages {
+    let mut result = None;
+    for extension in extensions.iter().filter(|e| e.matches(path)) {
+        match extension.parse(path, relative, url) {
             Err(e) => {
-                warn!("Failed to parse APT package {:?}: {:?}", path, e);
+                result = Some(false);
+                warn!(
+                    "Failed to parse {:?} with {} extension: {:?}",
+                    path,
+                    extension.name(),
+                    e
+                );
             }
             Ok(packages) => {
                 for package in packages {
-                    info!("APT package: {:?}", package);
-                    push_func(&package.into());
+                    info!("{} package: {:?}", extension.name(), package);
+                    push_func(&package);
                 }
+                result.get_or_insert(true);
             }
         }
     }
-    if args.yum_packages {
-        let is_primary = crate::extensions::yum::is_yum_primary_xml(path);
-        let is_repomd = crate::extensions::yum::is_yum_repomd_xml(path);
-        match (is_primary, is_repomd) {
-            (false, false) => (),
-            (p, r) => {
-                assert!(!(p && r), "File is both primary and repomd");
-                let xml_type = if p {
-                    crate::extensions::yum::YumXmlType::Primary
-                } else {
-                    crate::extensions::yum::YumXmlType::Repomd
-                };
-                let packages = yum::parse_package(path, relative, url, xml_type);
-                match packages {
-                    Err(e) => {
-                        warn!("Failed to parse YUM file {:?}: {:?}", path, e);
-                    }
-                    Ok(packages) => {
-                        for package in packages {
-                            info!("YUM package: {:?}", package);
-                            push_func(&package.into());
-                        }
-                    }
-                }
-            }
-        }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let apt = ExtensionType::Apt.build();
+        let yum = ExtensionType::Yum.build();
+        let inrelease = "debian/dists/bookworm/InRelease";
+        let repomd = "rocky/9/BaseOS/x86_64/os/repodata/repomd.xml";
+        assert!(apt.matches(Path::new(inrelease)));
+        assert!(apt.is_metadata(inrelease));
+        assert!(!apt.is_metadata("debian/pool/main/a/acl/acl_2.3.1-3_amd64.deb"));
+        assert!(!yum.matches(Path::new(inrelease)));
+        assert!(yum.matches(Path::new(repomd)));
+        assert!(yum.is_metadata("rocky/9/BaseOS/x86_64/os/repodata/1111-primary.xml.gz"));
+        assert!(!apt.is_metadata(repomd));
     }
 }
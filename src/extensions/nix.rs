@@ -0,0 +1,251 @@
+// Nix binary caches: <hash>.narinfo at cache root describes a store path, pointing to its NAR (like nar/<filehash>.nar.xz)
+// and referring to other store paths, whose narinfo are fetched recursively.
+// Binary caches have no listing, so sync starts from given store paths (see --nix-store-paths).
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use url::Url;
+
+use super::{read_compressed, ExtensionPackage};
+use crate::checksum::Checksum;
+
+const NIX32_ALPHABET: &[u8] = b"0123456789abcdfghijklmnpqrsvwxyz";
+const STORE_HASH_LEN: usize = 32;
+
+#[derive(Debug, Default, PartialEq)]
+struct NarInfo {
+    url: String,
+    file_hash: Option<String>,
+    file_size: Option<u64>,
+    /// Store path basenames, like "<hash>-glibc-2.38-44"
+    references: Vec<String>,
+}
+
+fn parse_narinfo(data: &str) -> Result<NarInfo> {
+    let mut narinfo = NarInfo::default();
+    for line in data.lines() {
+        match line.split_once(": ") {
+            Some(("URL", v)) => narinfo.url = v.trim().to_string(),
+            Some(("FileHash", v)) => narinfo.file_hash = Some(v.trim().to_string()),
+            Some(("FileSize", v)) => narinfo.file_size = v.trim().parse().ok(),
+            Some(("References", v)) => {
+                narinfo.references = v.split_whitespace().map(|s| s.to_string()).collect()
+            }
+            _ => {}
+        }
+    }
+    if narinfo.url.is_empty() {
+        return Err(anyhow!("No URL in narinfo"));
+    }
+    Ok(narinfo)
+}
+
+/// Decode Nix's base32 (used in FileHash), which is little-endian with its own alphabet.
+fn decode_nix32(s: &str) -> Option<Vec<u8>> {
+    let len = s.len() * 5 / 8;
+    let mut bytes = vec![0u8; len];
+    for (n, c) in s.bytes().rev().enumerate() {
+        let digit = NIX32_ALPHABET.iter().position(|&a| a == c)? as u16;
+        let (i, j) = (n * 5 / 8, n * 5 % 8);
+        let value = digit << j;
+        *bytes.get_mut(i)? |= value as u8;
+        if let Some(b) = bytes.get_mut(i + 1) {
+            *b |= (value >> 8) as u8;
+        } else if value >> 8 != 0 {
+            return None;
+        }
+    }
+    Some(bytes)
+}
+
+/// FileHash like "sha256:<nix32 or hex>"
+fn parse_file_hash(s: &str) -> Option<Checksum> {
+    let (algorithm, digest) = s.split_once(':')?;
+    let hex = if digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+        digest.to_string()
+    } else {
+        decode_nix32(digest)?
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    };
+    Checksum::new(algorithm, &hex)
+}
+
+/// Hash part of store path, like "/nix/store/<hash>-name" or "<hash>-name"
+fn store_path_hash(path: &str) -> Option<&str> {
+    let basename = path.trim().rsplit('/').next()?;
+    let hash = basename.get(..STORE_HASH_LEN)?;
+    (hash.bytes().all(|b| NIX32_ALPHABET.contains(&b))
+        && matches!(basename.as_bytes().get(STORE_HASH_LEN), None | Some(b'-')))
+    .then_some(hash)
+}
+
+fn narinfo_package(root_url: &Url, relative: &[String], hash: &str) -> Result<ExtensionPackage> {
+    let filename = format!("{hash}.narinfo");
+    Ok(ExtensionPackage {
+        url: root_url.join(&filename)?,
+        relative: relative.to_vec(),
+        filename,
+        size: None,
+        checksum: None,
+        optional: false,
+    })
+}
+
+/// Files to start syncing with: nix-cache-info, and narinfo of store paths listed in files (plain or compressed).
+pub fn seed_packages(store_paths: &[PathBuf], upstream: &Url) -> Result<Vec<ExtensionPackage>> {
+    let mut res = vec![ExtensionPackage {
+        url: upstream.join("nix-cache-info")?,
+        relative: vec![],
+        filename: "nix-cache-info".to_string(),
+        size: None,
+        checksum: None,
+        optional: false,
+    }];
+    for path in store_paths {
+        for line in read_compressed(path)?
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+        {
+            let hash = store_path_hash(line)
+                .ok_or_else(|| anyhow!("Invalid store path {:?} in {:?}", line, path))?;
+            res.push(narinfo_package(upstream, &[], hash)?);
+        }
+    }
+    Ok(res)
+}
+
+pub struct NixExtension;
+
+impl super::RepoExtension for NixExtension {
+    fn name(&self) -> &'static str {
+        "Nix"
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        path.extension().is_some_and(|e| e == "narinfo")
+    }
+
+    fn parse(&self, path: &Path, relative: &[String], url: &Url) -> Result<Vec<ExtensionPackage>> {
+        let narinfo = parse_narinfo(&std::fs::read_to_string(path)?)?;
+        let mut segments: Vec<String> = narinfo
+            .url
+            .split('/')
+            .filter(|s| !s.is_empty() && *s != ".")
+            .map(|s| s.to_string())
+            .collect();
+        let filename = segments
+            .pop()
+            .ok_or_else(|| anyhow!("Invalid URL in narinfo: {:?}", narinfo.url))?;
+        let mut nar_relative = relative.to_vec();
+        nar_relative.append(&mut segments);
+        let mut res = vec![ExtensionPackage {
+            url: url.join(&narinfo.url)?,
+            relative: nar_relative,
+            filename,
+            size: narinfo.file_size,
+            checksum: narinfo.file_hash.as_deref().and_then(parse_file_hash),
+            optional: false,
+        }];
+        for reference in &narinfo.references {
+            let hash = store_path_hash(reference)
+                .ok_or_else(|| anyhow!("Invalid reference {:?}", reference))?;
+            res.push(narinfo_package(url, relative, hash)?);
+        }
+        Ok(res)
+    }
+
+    fn is_metadata(&self, relative: &str) -> bool {
+        relative.ends_with(".narinfo")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::RepoExtension;
+
+    #[test]
+    fn test_nix32() {
+        assert_eq!(
+            parse_file_hash("sha256:0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c73"),
+            Checksum::new(
+                "sha256",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+            )
+        );
+        assert_eq!(
+            parse_file_hash("sha256:00xyyr3fi8l6hb839bv3f7yb86yjv7xi1cgh1xnhipym4asvb4aq"),
+            Checksum::new(
+                "sha256",
+                "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03"
+            )
+        );
+        assert_eq!(
+            parse_file_hash("sha256:0mdqa9w1p6cmli6976v4wi0sw9r4p5prkj7lzfd1877wk11c9c7e"),
+            None
+        );
+        assert_eq!(
+            store_path_hash("/nix/store/sr0hgd4k7x5dwyfpdkq2hllmlv4hmgp5-hello-2.12.1\n"),
+            Some("sr0hgd4k7x5dwyfpdkq2hllmlv4hmgp5")
+        );
+        assert_eq!(store_path_hash("/nix/store/short-hello"), None);
+    }
+
+    #[test]
+    fn test_narinfo() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/nix/sr0hgd4k7x5dwyfpdkq2hllmlv4hmgp5.narinfo");
+        assert!(NixExtension.matches(&path));
+        assert!(NixExtension.is_metadata("sr0hgd4k7x5dwyfpdkq2hllmlv4hmgp5.narinfo"));
+        assert!(!NixExtension.is_metadata("nix-cache-info"));
+
+        let url = Url::parse("https://cache.example.org/sr0hgd4k7x5dwyfpdkq2hllmlv4hmgp5.narinfo")
+            .unwrap();
+        let packages = NixExtension.parse(&path, &[], &url).unwrap();
+        assert_eq!(packages.len(), 3);
+        assert_eq!(
+            packages[0].url.as_str(),
+            "https://cache.example.org/nar/1f4kfm9vnm29bbx4bz8wk1nvl7bwg6m2wr7x3vcy6iqgcjpbcgsb.nar.xz"
+        );
+        assert_eq!(packages[0].relative, vec!["nar"]);
+        assert_eq!(
+            packages[0].filename,
+            "1f4kfm9vnm29bbx4bz8wk1nvl7bwg6m2wr7x3vcy6iqgcjpbcgsb.nar.xz"
+        );
+        assert_eq!(packages[0].size, Some(50088));
+        assert!(packages[0].checksum.is_some());
+        // Self reference is skipped later as already handled
+        let narinfos: Vec<&str> = packages[1..].iter().map(|p| p.url.as_str()).collect();
+        assert_eq!(
+            narinfos,
+            vec![
+                "https://cache.example.org/3n58xw4373jp0ljirf06d8077j15pc4j.narinfo",
+                "https://cache.example.org/sr0hgd4k7x5dwyfpdkq2hllmlv4hmgp5.narinfo",
+            ]
+        );
+        assert!(packages[1].relative.is_empty());
+    }
+
+    #[test]
+    fn test_seed_packages() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/nix/store-paths.xz");
+        let upstream = Url::parse("https://cache.example.org/").unwrap();
+        let packages = seed_packages(&[path], &upstream).unwrap();
+        let filenames: Vec<&str> = packages.iter().map(|p| p.filename.as_str()).collect();
+        assert_eq!(
+            filenames,
+            vec![
+                "nix-cache-info",
+                "sr0hgd4k7x5dwyfpdkq2hllmlv4hmgp5.narinfo",
+                "3n58xw4373jp0ljirf06d8077j15pc4j.narinfo",
+            ]
+        );
+        assert_eq!(
+            packages[1].url.as_str(),
+            "https://cache.example.org/sr0hgd4k7x5dwyfpdkq2hllmlv4hmgp5.narinfo"
+        );
+    }
+}
@@ -1,49 +1,87 @@
-use std::{io::Read, path::Path};
+use std::path::Path;
 
 use anyhow::Result;
-use flate2::read::GzDecoder;
-use tracing::info;
+use tracing::{info, warn};
 use url::Url;
 
-fn get_locations_from_xml(s: &str) -> Vec<String> {
-    let re = regex::Regex::new(r#"<location href="(.+?)".*/>"#).unwrap();
-    let mut urls = Vec::new();
-    for line in s.lines() {
-        if let Some(caps) = re.captures(line) {
-            let url = caps.get(1).unwrap().as_str();
-            urls.push(url.to_string());
-        }
+use super::read_compressed;
+use crate::checksum::Checksum;
+
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+
+#[derive(Debug, PartialEq)]
+struct YumLocation {
+    pub href: String,
+    /// xml:base of location, when the file is hosted elsewhere
+    pub base: Option<String>,
+    pub size: Option<u64>,
+    pub checksum: Option<Checksum>,
+}
+
+// Both <package> in primary.xml and <data> in repomd.xml are like:
+// <checksum type="sha256">...</checksum> <location href="..."/> <size package="123"/> (or <size>123</size>)
+fn get_locations_from_xml(s: &str) -> Result<Vec<YumLocation>> {
+    let document = roxmltree::Document::parse(s)?;
+    let mut locations = Vec::new();
+    for node in document
+        .root_element()
+        .children()
+        .filter(|n| matches!(n.tag_name().name(), "package" | "data"))
+    {
+        let child = |name: &str| node.children().find(|n| n.tag_name().name() == name);
+        let Some(location) = child("location") else {
+            warn!("No location in {:?}", node.attribute("type"));
+            continue;
+        };
+        let Some(href) = location.attribute("href") else {
+            warn!("No href in location of {:?}", node.attribute("type"));
+            continue;
+        };
+        let size = child("size")
+            .and_then(|n| n.attribute("package").or_else(|| n.text()))
+            .and_then(|s| s.trim().parse().ok());
+        let checksum =
+            child("checksum").and_then(|n| Checksum::new(n.attribute("type")?, n.text()?.trim()));
+        locations.push(YumLocation {
+            href: href.to_string(),
+            base: location.attribute((XML_NS, "base")).map(|s| s.to_string()),
+            size,
+            checksum,
+        });
     }
-    urls
+    Ok(locations)
 }
 
-pub fn is_yum_primary_xml(p: &Path) -> bool {
+fn is_yum_primary_xml(p: &Path) -> bool {
     p.file_name()
-        .map(|f| f.to_str().unwrap())
-        .map(|f| f.ends_with("primary.xml.gz"))
+        .and_then(|f| f.to_str())
+        .map(|f| {
+            let f = ["gz", "xz", "bz2", "zst"]
+                .iter()
+                .find_map(|ext| f.strip_suffix(&format!(".{ext}")))
+                .unwrap_or(f);
+            f == "primary.xml" || f.ends_with("-primary.xml")
+        })
         .unwrap_or(false)
 }
 
 // read and extract location
-pub fn read_primary_xml(p: &Path) -> Result<Vec<String>> {
-    let bytes = std::fs::read(p)?;
-    let mut gzd = GzDecoder::new(&bytes[..]);
-    let mut s = String::new();
-    gzd.read_to_string(&mut s)?;
-
-    Ok(get_locations_from_xml(&s))
+fn read_primary_xml(p: &Path) -> Result<Vec<YumLocation>> {
+    get_locations_from_xml(&read_compressed(p)?)
 }
 
-pub enum YumXmlType {
+enum YumXmlType {
     Primary,
     Repomd,
 }
 
 #[derive(Debug)]
-pub struct YumPackage {
+struct YumPackage {
     pub url: Url,
     pub relative: Vec<String>,
     pub filename: String,
+    pub size: Option<u64>,
+    pub checksum: Option<Checksum>,
 }
 
 impl From<YumPackage> for super::ExtensionPackage {
@@ -52,11 +90,28 @@ impl From<YumPackage> for super::ExtensionPackage {
             url: val.url,
             relative: val.relative,
             filename: val.filename,
+            size: val.size,
+            checksum: val.checksum,
+            optional: false,
+        }
+    }
+}
+
+/// URL of location, which is relative to xml:base if set, otherwise to repo root.
+fn location_url(base_url: &Url, location: &YumLocation) -> Result<Url> {
+    match &location.base {
+        Some(base) => {
+            let mut base = base_url.join(base)?;
+            if !base.path().ends_with('/') {
+                base.path_segments_mut().unwrap().push("");
+            }
+            Ok(base.join(&location.href)?)
         }
+        None => Ok(base_url.join(&location.href)?),
     }
 }
 
-pub fn parse_package(
+fn parse_package(
     packages_path: &Path,
     relative: &[String],
     packages_url: &Url,
@@ -76,8 +131,8 @@ pub fn parse_package(
 
     let mut res = vec![];
     for package in packages {
-        let url = base_url.join(&package)?;
-        let splited: Vec<String> = package.split('/').map(|s| s.to_string()).collect();
+        let url = location_url(&base_url, &package)?;
+        let splited: Vec<String> = package.href.split('/').map(|s| s.to_string()).collect();
         let mut relative = relative.clone();
         relative.append(&mut splited.clone());
 
@@ -86,6 +141,8 @@ pub fn parse_package(
             url,
             relative,
             filename: basename,
+            size: package.size,
+            checksum: package.checksum,
         })
     }
 
@@ -95,16 +152,224 @@ pub fn parse_package(
 // Well, brain-damaged mysql-repo even cannot show all primary.xml.gz...
 // So I have to use repomd.xml to get primary.xml.gz...
 // Good news is that it seems like existing functions for handling primary.xml.gz can be reused.
-pub fn is_yum_repomd_xml(p: &Path) -> bool {
+fn is_yum_repomd_xml(p: &Path) -> bool {
     p.file_name()
         .map(|f| f.to_str().unwrap())
         .map(|f| f == "repomd.xml")
         .unwrap_or(false)
 }
 
-pub fn read_yum_repomd_xml(p: &Path) -> Result<Vec<String>> {
-    let bytes = std::fs::read(p)?;
-    let s = String::from_utf8_lossy(&bytes);
+// All <data> are returned: primary, filelists, other, updateinfo, modules, group, and their zchunk variants.
+fn read_yum_repomd_xml(p: &Path) -> Result<Vec<YumLocation>> {
+    get_locations_from_xml(&std::fs::read_to_string(p)?)
+}
+
+pub struct YumExtension;
+
+impl super::RepoExtension for YumExtension {
+    fn name(&self) -> &'static str {
+        "YUM"
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        is_yum_primary_xml(path) || is_yum_repomd_xml(path)
+    }
+
+    fn parse(
+        &self,
+        path: &Path,
+        relative: &[String],
+        url: &Url,
+    ) -> Result<Vec<super::ExtensionPackage>> {
+        let xml_type = if is_yum_primary_xml(path) {
+            YumXmlType::Primary
+        } else {
+            YumXmlType::Repomd
+        };
+        Ok(parse_package(path, relative, url, xml_type)?
+            .into_iter()
+            .map(|p| p.into())
+            .collect())
+    }
 
-    Ok(get_locations_from_xml(s.as_ref()))
+    fn is_metadata(&self, relative: &str) -> bool {
+        relative.split('/').any(|s| s == "repodata")
+    }
+
+    fn is_package(&self, relative: &str) -> bool {
+        relative.ends_with(".rpm")
+    }
+
+    /// Locations in primary.xml are relative to the parent of repodata/
+    fn package_root<'a>(&self, dir: &'a Path) -> &'a Path {
+        match dir.parent() {
+            Some(parent) if dir.file_name().is_some_and(|f| f == "repodata") => parent,
+            _ => dir,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::RepoExtension;
+
+    #[test]
+    fn test_locations() {
+        let primary = r#"<metadata xmlns="http://linux.duke.edu/metadata/common" packages="2">
+<package type="rpm">
+  <name>mysql-community-client</name>
+  <checksum type="sha256" pkgid="YES">6A0C1B2F3E4D5C6B7A8091A2B3C4D5E6F708192A3B4C5D6E7F8091A2B3C4D5E6</checksum>
+  <size package="17389424" installed="97545936" archive="97566948"/>
+  <location href="Packages/mysql-community-client-8.0.36-1.el9.x86_64.rpm"/>
+</package>
+<package type="rpm">
+  <name>old</name>
+  <checksum type="sha" pkgid="YES">da39a3ee5e6b4b0d3255bfef95601890afd80709</checksum>
+  <location href="Packages/old.rpm"/>
+</package>
+</metadata>"#;
+        let locations = get_locations_from_xml(primary).unwrap();
+        assert_eq!(locations.len(), 2);
+        assert_eq!(
+            locations[0].href,
+            "Packages/mysql-community-client-8.0.36-1.el9.x86_64.rpm"
+        );
+        assert_eq!(locations[0].size, Some(17389424));
+        assert_eq!(
+            locations[0].checksum,
+            Some(Checksum::Sha256(
+                "6a0c1b2f3e4d5c6b7a8091a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6".to_string()
+            ))
+        );
+        // sha1 is not supported
+        assert_eq!(locations[1].size, None);
+        assert_eq!(locations[1].checksum, None);
+
+        let repomd = r#"<repomd xmlns="http://linux.duke.edu/metadata/repo">
+  <data type="primary">
+    <checksum type="sha256">5e2b4e4f0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5</checksum>
+    <open-checksum type="sha256">0000000000000000000000000000000000000000000000000000000000000000</open-checksum>
+    <location href="repodata/5e2b-primary.xml.gz"/>
+    <timestamp>1709900000</timestamp>
+    <size>12345</size>
+    <open-size>99999</open-size>
+  </data>
+</repomd>"#;
+        let locations = get_locations_from_xml(repomd).unwrap();
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].href, "repodata/5e2b-primary.xml.gz");
+        assert_eq!(locations[0].size, Some(12345));
+        assert!(
+            matches!(locations[0].checksum, Some(Checksum::Sha256(ref s)) if s.starts_with("5e2b"))
+        );
+    }
+
+    #[test]
+    fn test_locations_xml() {
+        let primary = r#"<?xml version="1.0" encoding="UTF-8"?>
+<metadata xmlns="http://linux.duke.edu/metadata/common" xmlns:rpm="http://linux.duke.edu/metadata/rpm" packages="2">
+<package type="rpm">
+  <name>libstdc++</name>
+  <checksum type="sha256"
+    pkgid="YES">4a5b6c7d8e9f00112233445566778899aabbccddeeff00112233445566778899</checksum>
+  <size package="912345"
+    installed="2800000" archive="2801000"/>
+  <location
+    xml:base="https://vault.example.com/9.2/BaseOS/x86_64/os"
+    href="Packages/l/libstdc++-11.3.1-4.el9.x86_64.rpm"/>
+</package>
+<package type="rpm">
+  <name>r&amp;d-tools</name>
+  <location href="Packages/r/r&amp;d-tools-1.0-1.noarch.rpm"/>
+</package>
+</metadata>"#;
+        let locations = get_locations_from_xml(primary).unwrap();
+        assert_eq!(locations.len(), 2);
+        assert_eq!(
+            locations[0].href,
+            "Packages/l/libstdc++-11.3.1-4.el9.x86_64.rpm"
+        );
+        assert_eq!(locations[0].size, Some(912345));
+        assert!(locations[0].checksum.is_some());
+        assert_eq!(locations[1].href, "Packages/r/r&d-tools-1.0-1.noarch.rpm");
+        assert_eq!(locations[1].base, None);
+
+        let base_url = Url::parse("http://localhost/rocky/9/BaseOS/x86_64/os/").unwrap();
+        assert_eq!(
+            location_url(&base_url, &locations[0]).unwrap().as_str(),
+            "https://vault.example.com/9.2/BaseOS/x86_64/os/Packages/l/libstdc++-11.3.1-4.el9.x86_64.rpm"
+        );
+        assert_eq!(
+            location_url(&base_url, &locations[1]).unwrap().as_str(),
+            "http://localhost/rocky/9/BaseOS/x86_64/os/Packages/r/r&d-tools-1.0-1.noarch.rpm"
+        );
+
+        let repomd = r#"<?xml version="1.0" encoding="UTF-8"?>
+<repomd xmlns="http://linux.duke.edu/metadata/repo" xmlns:rpm="http://linux.duke.edu/metadata/rpm">
+  <revision>1709900000</revision>
+  <data type="primary">
+    <checksum type="sha256">1111111111111111111111111111111111111111111111111111111111111111</checksum>
+    <location href="repodata/1111-primary.xml.zst"/>
+    <size>100</size>
+  </data>
+  <data type="filelists">
+    <checksum type="sha256">2222222222222222222222222222222222222222222222222222222222222222</checksum>
+    <location href="repodata/2222-filelists.xml.zst"/>
+    <size>200</size>
+  </data>
+  <data type="updateinfo">
+    <location href="repodata/3333-updateinfo.xml.xz"/>
+  </data>
+  <data type="modules">
+    <location href="repodata/4444-modules.yaml.gz"/>
+  </data>
+  <data type="group">
+    <location href="repodata/5555-comps-BaseOS.x86_64.xml"/>
+  </data>
+  <data type="primary_zck">
+    <location href="repodata/6666-primary.xml.zck"/>
+    <size>300</size>
+    <header-size>1024</header-size>
+  </data>
+</repomd>"#;
+        let locations = get_locations_from_xml(repomd).unwrap();
+        assert_eq!(locations.len(), 6);
+        assert_eq!(locations[1].href, "repodata/2222-filelists.xml.zst");
+        assert_eq!(locations[1].size, Some(200));
+        assert_eq!(locations[5].size, Some(300));
+    }
+
+    #[test]
+    fn test_is_primary() {
+        assert!(is_yum_primary_xml(Path::new(
+            "repodata/1111-primary.xml.gz"
+        )));
+        assert!(is_yum_primary_xml(Path::new(
+            "repodata/1111-primary.xml.zst"
+        )));
+        assert!(is_yum_primary_xml(Path::new("repodata/primary.xml.bz2")));
+        assert!(is_yum_primary_xml(Path::new("repodata/primary.xml")));
+        assert!(!is_yum_primary_xml(Path::new(
+            "repodata/1111-primary.xml.zck"
+        )));
+        assert!(!is_yum_primary_xml(Path::new(
+            "repodata/1111-primary.sqlite.bz2"
+        )));
+        assert!(!is_yum_primary_xml(Path::new(
+            "repodata/2222-filelists.xml.gz"
+        )));
+    }
+
+    #[test]
+    fn test_package_root() {
+        assert_eq!(
+            YumExtension.package_root(Path::new("/srv/epel/9/Everything/x86_64/repodata")),
+            Path::new("/srv/epel/9/Everything/x86_64")
+        );
+        assert!(
+            YumExtension.is_package("epel/9/Everything/x86_64/Packages/a/abc-1.0-1.el9.x86_64.rpm")
+        );
+        assert!(!YumExtension.is_package("epel/9/Everything/x86_64/repodata/repomd.xml"));
+    }
 }
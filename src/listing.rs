@@ -8,6 +8,7 @@ use reqwest::blocking::Client;
 use tracing::{debug, info};
 use url::Url;
 
+use crate::checksum::Checksum;
 use crate::parser;
 use crate::utils;
 
@@ -59,7 +60,6 @@ pub enum FileSize {
     Precise(u64),
     /// 1024B -> 1KiB
     HumanizedBinary(f64, SizeUnit),
-    #[allow(dead_code)]
     /// 1000B -> 1KB
     HumanizedDecimal(f64, SizeUnit),
 }
@@ -75,7 +75,8 @@ impl Display for FileSize {
 }
 
 impl FileSize {
-    pub fn get_humanized(s: &str) -> (f64, SizeUnit) {
+    /// Parse humanized size like "1.5 KiB". Returns None if it is not a valid one.
+    pub fn get_humanized(s: &str) -> Option<(f64, SizeUnit)> {
         // seperate numeric and unit
         let mut numeric = String::new();
         let mut unit = String::new();
@@ -89,7 +90,7 @@ impl FileSize {
         let unit = unit.to_lowercase();
         let unit = unit.trim();
 
-        let numeric = numeric.parse::<f64>().unwrap();
+        let numeric = numeric.parse::<f64>().ok()?;
         let unit = match unit.chars().next() {
             None => SizeUnit::B,
             Some(u) => match u {
@@ -99,11 +100,11 @@ impl FileSize {
                 'g' => SizeUnit::G,
                 't' => SizeUnit::T,
                 'p' => SizeUnit::P,
-                _ => panic!("Unknown unit: {unit}"),
+                _ => return None,
             },
         };
 
-        (numeric, unit)
+        Some((numeric, unit))
     }
 
     pub fn get_estimated(&self) -> u64 {
@@ -129,9 +130,20 @@ pub struct ListItem {
     pub size: Option<FileSize>,
     /// mtime is parsed from HTML, which is the local datetime of the "server" (not necessarily localtime or UTC)
     pub mtime: NaiveDateTime,
+    /// Timezone of mtime, if the listing format tells it (e.g. nginx JSON/XML autoindex in UTC).
+    /// This overrides the guessed (or given) timezone.
+    pub timezone: Option<FixedOffset>,
+    /// ETag of file, if the listing format tells it (e.g. S3 bucket listing).
+    pub etag: Option<String>,
+    /// Checksum of file, if known from repository metadata (e.g. APT Packages).
+    pub checksum: Option<Checksum>,
     /// Don't check size and mtime: download only if the file doesn't exist.
-    /// This is expected to be set by apt/yum parser extension (parser will not use this).
+    /// This is expected to be set by extensions, and parsers of immutable files with checksums (like PyPI).
     pub skip_check: bool,
+    /// The file might not exist (e.g. signatures found by extensions), so that 404 is not an error.
+    pub optional: bool,
+    /// Extra attributes to keep in local index (e.g. data-requires-python in PyPI simple index).
+    pub attributes: Vec<(String, String)>,
 }
 
 impl ListItem {
@@ -148,9 +160,19 @@ impl ListItem {
             type_,
             size,
             mtime,
+            timezone: None,
+            etag: None,
+            checksum: None,
             skip_check: false,
+            optional: false,
+            attributes: vec![],
         }
     }
+
+    pub fn with_timezone(mut self, timezone: FixedOffset) -> Self {
+        self.timezone = Some(timezone);
+        self
+    }
 }
 
 impl Display for ListItem {
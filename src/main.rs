@@ -4,23 +4,27 @@ use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 
 use parser::ParserType;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, EnvFilter};
 use url::Url;
 
 use shadow_rs::shadow;
 shadow!(build);
 
+mod checksum;
 mod cli;
 mod compare;
 mod listing;
 mod parser;
 mod regex_process;
+mod signature;
+mod staging;
+mod state;
 mod term;
 mod utils;
 
 mod extensions;
 
-use crate::regex_process::ExpandedRegex;
+use crate::{extensions::ExtensionType, regex_process::ExpandedRegex};
 
 #[derive(Parser, Debug)]
 #[command(about)]
@@ -34,10 +38,13 @@ struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Sync files from upstream to local.
-    Sync(SyncArgs),
+    Sync(Box<SyncArgs>),
 
     /// List files from upstream.
     List(ListArgs),
+
+    /// Parse a saved listing file, for debugging parsers.
+    Parse(ParseArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -90,6 +97,15 @@ pub struct SyncArgs {
     #[clap(long, value_enum, default_value_t = ParserType::Nginx)]
     parser: ParserType,
 
+    /// YAML description of listing layout for generic parser.
+    #[clap(long)]
+    parser_config: Option<PathBuf>,
+
+    /// Write index pages generated by the parser (like PyPI simple index for pip) into local directories,
+    /// after files are synced.
+    #[clap(long)]
+    local_index: bool,
+
     /// Excluded file regex. Supports multiple.
     #[clap(long, value_parser)]
     exclude: Vec<ExpandedRegex>,
@@ -110,13 +126,53 @@ pub struct SyncArgs {
     #[clap(long)]
     allow_mtime_from_parser: bool,
 
-    /// (Experimental) APT Packages file parser to find out missing packages.
+    /// Keep sync state in this file (e.g. <LOCAL>/.tsumugu-state.json) to skip checks of files unchanged since last run.
+    #[clap(long)]
+    state_file: Option<PathBuf>,
+
+    /// Download metadata (matching --metadata-regex) into a staging dir, and publish them after all other files are synced.
+    /// Deletion is also deferred until then.
+    #[clap(long)]
+    metadata_last: bool,
+
+    /// Metadata file regex for --metadata-last. Supports multiple.
+    #[clap(long, value_parser, default_values = ["(^|/)dists/", "(^|/)repodata/"])]
+    metadata_regex: Vec<ExpandedRegex>,
+
+    /// Verify OpenPGP signatures of metadata (InRelease, Release.gpg, repomd.xml.asc) with keys in this file (armored or binary). Supports multiple.
+    /// Metadata is staged as with --metadata-last, and not published if verification fails (exit code 5).
+    #[clap(long)]
+    keyring: Vec<PathBuf>,
+
+    /// (Experimental) Repository extensions to find out missing files from metadata. Supports multiple (comma separated).
+    #[clap(long, value_enum, value_delimiter = ',')]
+    extension: Vec<ExtensionType>,
+
+    /// (Experimental) APT index parser (InRelease/Release, Packages and Sources) to find out missing index files and packages.
+    /// Same as "--extension apt".
     #[clap(long)]
     apt_packages: bool,
 
     /// (Experimental) YUM Packages file parser to find out missing packages.
+    /// Same as "--extension yum".
     #[clap(long)]
     yum_packages: bool,
+
+    /// Verify checksums of existing packages found by APT/YUM parser, and repair mismatched ones.
+    /// This reads every package file. Downloaded packages are always verified.
+    #[clap(long)]
+    verify_checksum: bool,
+
+    /// Delete packages (of extensions supporting it: apt pool/, yum .rpm, conda and helm) which are not referred by metadata,
+    /// even if they are still listed by remote, or --no-delete is set. Only repos whose metadata are all parsed successfully
+    /// are checked, and --max-delete still applies.
+    #[clap(long)]
+    delete_unreferenced: bool,
+
+    /// Files listing Nix store paths (one per line, like store-paths.xz of NixOS releases, plain or compressed) to sync from a Nix binary cache.
+    /// Instead of listing upstream, their narinfo and NARs are synced, following references recursively. Implies "--extension nix". Supports multiple.
+    #[clap(long)]
+    nix_store_paths: Vec<PathBuf>,
 }
 
 #[derive(Parser, Debug)]
@@ -133,6 +189,10 @@ pub struct ListArgs {
     #[clap(long, value_enum, default_value_t=ParserType::Nginx)]
     parser: ParserType,
 
+    /// YAML description of listing layout for generic parser.
+    #[clap(long)]
+    parser_config: Option<PathBuf>,
+
     /// Excluded file regex. Supports multiple.
     #[clap(long, value_parser)]
     exclude: Vec<ExpandedRegex>,
@@ -146,14 +206,44 @@ pub struct ListArgs {
     upstream_base: String,
 }
 
+#[derive(Parser, Debug)]
+pub struct ParseArgs {
+    /// Choose a parser.
+    #[clap(long, value_enum, default_value_t=ParserType::Nginx)]
+    parser: ParserType,
+
+    /// YAML description of listing layout for generic parser.
+    #[clap(long)]
+    parser_config: Option<PathBuf>,
+
+    /// The URL where the listing comes from. A trailing "/" is added if missing.
+    #[clap(long)]
+    base_url: Url,
+
+    /// Print items as JSON lines.
+    #[clap(long)]
+    json: bool,
+
+    /// The listing file, or "-" for stdin.
+    #[clap(value_parser)]
+    file: PathBuf,
+}
+
 fn main() {
     // https://github.com/tokio-rs/tracing/issues/735#issuecomment-957884930
     std::env::set_var(
         "RUST_LOG",
         format!("info,{}", std::env::var("RUST_LOG").unwrap_or_default()),
     );
+    let args = Cli::parse();
     let enable_color = std::env::var("NO_COLOR").is_err();
+    // Keep stdout clean for parse output
+    let writer = match args.command {
+        Commands::Parse(_) => BoxMakeWriter::new(std::io::stderr),
+        _ => BoxMakeWriter::new(std::io::stdout),
+    };
     tracing_subscriber::fmt()
+        .with_writer(writer)
         .with_thread_ids(true)
         .with_env_filter(EnvFilter::from_default_env())
         .with_ansi(enable_color)
@@ -181,7 +271,6 @@ fn main() {
         std::process::exit(3);
     }));
 
-    let args = Cli::parse();
     match args.command {
         Commands::Sync(args) => {
             cli::sync(&args, bind_address);
@@ -193,5 +282,8 @@ fn main() {
             }
             cli::list(&args, bind_address);
         }
+        Commands::Parse(args) => {
+            cli::parse(&args);
+        }
     };
 }
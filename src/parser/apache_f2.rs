@@ -1,14 +1,10 @@
 // https://httpd.apache.org/docs/2.4/mod/mod_autoindex.html
 // > F=2 formats the listing as an HTMLTable FancyIndexed list
 
-use crate::{
-    listing::{FileSize, FileType, ListItem},
-    utils::get,
-};
+use crate::listing::{FileType, ListItem};
 
 use super::*;
 use anyhow::Result;
-use chrono::NaiveDateTime;
 use scraper::{Html, Selector};
 // use tracing::debug;
 
@@ -16,28 +12,35 @@ use scraper::{Html, Selector};
 pub struct ApacheF2ListingParser;
 
 impl Parser for ApacheF2ListingParser {
-    fn get_list(&self, client: &reqwest::blocking::Client, url: &url::Url) -> Result<ListResult> {
-        let resp = get(client, url.clone())?;
-        let url = resp.url().clone();
-        let body = resp.text()?;
-        assert_if_url_has_no_trailing_slash(&url);
-        let document = Html::parse_document(&body);
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let document = Html::parse_document(body);
         // find #indexlist which contains file index
         let selector = Selector::parse("#indexlist").unwrap();
-        let indexlist = document.select(&selector).next().unwrap();
+        let indexlist = document
+            .select(&selector)
+            .next()
+            .ok_or_else(|| ParseError::layout(url, "cannot find #indexlist", body))?;
         // iterate its child finding .odd and .even
         let selector = Selector::parse("tr.odd, tr.even").unwrap();
         let mut items = Vec::new();
         for element in indexlist.select(&selector) {
+            let layout_error = |reason| ParseError::layout(url, reason, &element.html());
             // find <a> tag with indexcolname class
             let selector = Selector::parse("td.indexcolname a").unwrap();
-            let a = element.select(&selector).next().unwrap();
+            let a = element
+                .select(&selector)
+                .next()
+                .ok_or_else(|| layout_error("cannot find td.indexcolname a"))?;
             let displayed_filename = a.inner_html();
             if displayed_filename == "Parent Directory" {
                 continue;
             }
 
-            let href = a.value().attr("href").unwrap();
+            let href = a
+                .value()
+                .attr("href")
+                .ok_or_else(|| layout_error("cannot find href inside <a>"))?;
             let name = get_real_name_from_href(href);
             let href = url.join(href)?;
             let type_ = if href.as_str().ends_with('/') {
@@ -47,31 +50,31 @@ impl Parser for ApacheF2ListingParser {
             };
             // lastmod
             let selector = Selector::parse("td.indexcollastmod").unwrap();
-            let lastmod = element.select(&selector).next().unwrap().inner_html();
+            let lastmod = element
+                .select(&selector)
+                .next()
+                .ok_or_else(|| layout_error("cannot find td.indexcollastmod"))?
+                .inner_html();
             let lastmod = lastmod.trim();
             // size
             let selector = Selector::parse("td.indexcolsize").unwrap();
-            let size = element.select(&selector).next().unwrap().inner_html();
+            let size = element
+                .select(&selector)
+                .next()
+                .ok_or_else(|| layout_error("cannot find td.indexcolsize"))?
+                .inner_html();
             let size = size.trim();
 
             // debug!("{} {} {} {}", href, name, lastmod, size);
 
-            let date = NaiveDateTime::parse_from_str(lastmod, "%Y-%m-%d %H:%M")?;
+            let date = parse_date(url, lastmod, &["%Y-%m-%d %H:%M"])?;
+            let size = if size == "-" {
+                None
+            } else {
+                Some(parse_humanized_binary(url, size)?)
+            };
 
-            items.push(ListItem::new(
-                href,
-                name.to_string(),
-                type_,
-                {
-                    if size == "-" {
-                        None
-                    } else {
-                        let (n_size, unit) = FileSize::get_humanized(size);
-                        Some(FileSize::HumanizedBinary(n_size, unit))
-                    }
-                },
-                date,
-            ))
+            items.push(ListItem::new(href, name.to_string(), type_, size, date))
         }
 
         Ok(ListResult::List(items))
@@ -80,19 +83,14 @@ impl Parser for ApacheF2ListingParser {
 
 #[cfg(test)]
 mod tests {
-    use crate::listing::SizeUnit;
+    use crate::listing::{FileSize, SizeUnit};
+    use chrono::NaiveDateTime;
 
     use super::*;
 
     #[test]
     fn test_winehq_root() {
-        let client = reqwest::blocking::Client::new();
-        let items = ApacheF2ListingParser
-            .get_list(
-                &client,
-                &url::Url::parse("http://localhost:1921/wine-builds").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(&ApacheF2ListingParser, "http://localhost:1921/wine-builds/");
         match items {
             ListResult::List(items) => {
                 assert_eq!(items.len(), 8);
@@ -0,0 +1,255 @@
+// A parser which fingerprints the listing HTML and delegates to a suitable parser.
+// Detection is done for every directory, as some upstreams mix server software under different paths.
+
+use std::sync::Mutex;
+
+use super::*;
+use anyhow::Result;
+use regex::Regex;
+use scraper::{Html, Selector};
+use tracing::{debug, info};
+
+pub struct AutoListingParser {
+    parsers: Vec<(ParserType, Box<dyn Parser>)>,
+    iso_date_regex: Regex,
+    nginx_date_regex: Regex,
+    last_detected: Mutex<Option<ParserType>>,
+}
+
+impl Default for AutoListingParser {
+    fn default() -> Self {
+        // Build parsers directly to avoid warnings in ParserType::build()
+        let parsers: Vec<(ParserType, Box<dyn Parser>)> = vec![
+            (
+                ParserType::Nginx,
+                Box::<nginx::NginxListingParser>::default(),
+            ),
+            (
+                ParserType::ApacheF2,
+                Box::<apache_f2::ApacheF2ListingParser>::default(),
+            ),
+            (
+                ParserType::Docker,
+                Box::<docker::DockerListingParser>::default(),
+            ),
+            (
+                ParserType::DirectoryLister,
+                Box::<directory_lister::DirectoryListerListingParser>::default(),
+            ),
+            (
+                ParserType::Lighttpd,
+                Box::<lighttpd::LighttpdListingParser>::default(),
+            ),
+            (
+                ParserType::Caddy,
+                Box::<caddy::CaddyListingParser>::default(),
+            ),
+            (
+                ParserType::FancyIndex,
+                Box::<fancyindex::FancyIndexListingParser>::default(),
+            ),
+            (
+                ParserType::NginxJson,
+                Box::<nginx_json::NginxJsonListingParser>::default(),
+            ),
+            (
+                ParserType::NginxXml,
+                Box::<nginx_xml::NginxXmlListingParser>::default(),
+            ),
+            (ParserType::Ftp, Box::<ftp::FtpListingParser>::default()),
+        ];
+        Self {
+            parsers,
+            iso_date_regex: Regex::new(r"\d{4}-\d{2}-\d{2} \d{2}:\d{2}").unwrap(),
+            nginx_date_regex: Regex::new(r"\d{2}-\w{3}-\d{4} \d{2}:\d{2}").unwrap(),
+            last_detected: Mutex::new(None),
+        }
+    }
+}
+
+impl AutoListingParser {
+    /// Guess parser type by the "Server" header (if any) and HTML body.
+    pub fn detect(&self, server: Option<&str>, body: &str) -> Option<ParserType> {
+        // Nginx autoindex in non-HTML formats
+        let trimmed = body.trim_start();
+        if trimmed.starts_with('[') {
+            return Some(ParserType::NginxJson);
+        }
+        if trimmed.starts_with("<?xml") && trimmed.contains("<list>") {
+            return Some(ParserType::NginxXml);
+        }
+        let document = Html::parse_document(body);
+        let has = |s: &str| {
+            document
+                .select(&Selector::parse(s).unwrap())
+                .next()
+                .is_some()
+        };
+
+        // Markers which are specific enough to be trusted first
+        if has("table#indexlist") {
+            return Some(ParserType::ApacheF2);
+        }
+        if has("tr.file td a") {
+            return Some(ParserType::Caddy);
+        }
+        if has("tbody tr td.link a") {
+            return Some(ParserType::FancyIndex);
+        }
+        if has("tbody tr td.n a") && has("tbody tr td.m") {
+            return Some(ParserType::Lighttpd);
+        }
+        if has("ul li a div.flex-1.truncate") {
+            return Some(ParserType::DirectoryLister);
+        }
+        if let Some(pre) = document.select(&Selector::parse("pre").unwrap()).next() {
+            let text = pre.text().collect::<String>();
+            if self.nginx_date_regex.is_match(&text) {
+                return Some(ParserType::Nginx);
+            }
+            if self.iso_date_regex.is_match(&text) {
+                return Some(ParserType::Docker);
+            }
+        }
+        // Fallback to server header
+        let server = server?.to_lowercase();
+        if server.starts_with("nginx") || server.starts_with("openresty") {
+            Some(ParserType::Nginx)
+        } else if server.starts_with("lighttpd") {
+            Some(ParserType::Lighttpd)
+        } else if server.starts_with("caddy") {
+            Some(ParserType::Caddy)
+        } else {
+            None
+        }
+    }
+
+    fn parse_detected(&self, server: Option<&str>, body: &str, url: &Url) -> Result<ListResult> {
+        let type_ = self
+            .detect(server, body)
+            .ok_or_else(|| ParseError::layout(url, "cannot detect a suitable parser", body))?;
+        {
+            let mut last_detected = self.last_detected.lock().unwrap();
+            if *last_detected != Some(type_) {
+                info!("Auto detected parser {:?} for {}", type_, url);
+                *last_detected = Some(type_);
+            } else {
+                debug!("Auto detected parser {:?} for {}", type_, url);
+            }
+        }
+        self.get_parser(type_).parse_body(body, url)
+    }
+
+    fn get_parser(&self, type_: ParserType) -> &dyn Parser {
+        let (_, parser) = self.parsers.iter().find(|(t, _)| *t == type_).unwrap();
+        parser.as_ref()
+    }
+}
+
+impl Parser for AutoListingParser {
+    fn is_auto_redirect(&self) -> bool {
+        // Redirects of directories are kept as symlinks, like Docker listing
+        false
+    }
+
+    fn get_list(&self, client: &Client, url: &Url) -> Result<ListResult> {
+        if url.scheme() == "ftp" {
+            return self.get_parser(ParserType::Ftp).get_list(client, url);
+        }
+        let resp = get(client, url.clone())?;
+        if let Some(redirect) = docker::redirect_of(&resp)? {
+            return Ok(redirect);
+        }
+        let url = resp.url().clone();
+        let server = resp
+            .headers()
+            .get("server")
+            .and_then(|s| s.to_str().ok())
+            .map(|s| s.to_string());
+        let body = resp.text()?;
+        self.parse_detected(server.as_deref(), &body, &url)
+    }
+
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        if url.scheme() == "ftp" {
+            return self.get_parser(ParserType::Ftp).parse_body(body, url);
+        }
+        self.parse_detected(None, body, url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_fixture(name: &str) -> Option<ParserType> {
+        let path = format!(
+            "{}/fixtures/{}/index.html",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+        let body = std::fs::read_to_string(path).unwrap();
+        AutoListingParser::default().detect(None, &body)
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(
+            detect_fixture("monitoring-plugins"),
+            Some(ParserType::Nginx)
+        );
+        assert_eq!(detect_fixture("proxmox"), Some(ParserType::Nginx));
+        assert_eq!(detect_fixture("mysql"), Some(ParserType::Nginx));
+        assert_eq!(detect_fixture("wine-builds"), Some(ParserType::ApacheF2));
+        assert_eq!(detect_fixture("docker"), Some(ParserType::Docker));
+        assert_eq!(detect_fixture("docker/armv7l"), Some(ParserType::Docker));
+        assert_eq!(detect_fixture("vyos"), Some(ParserType::DirectoryLister));
+        assert_eq!(detect_fixture("buildroot"), Some(ParserType::Lighttpd));
+        assert_eq!(detect_fixture("sdumirror-ubuntu"), Some(ParserType::Caddy));
+        assert_eq!(detect_fixture("bmclapi"), Some(ParserType::FancyIndex));
+        assert_eq!(detect_fixture("loongnix"), Some(ParserType::FancyIndex));
+        assert_eq!(detect_fixture("nginx-json"), Some(ParserType::NginxJson));
+        assert_eq!(detect_fixture("nginx-xml"), Some(ParserType::NginxXml));
+    }
+
+    #[test]
+    fn test_detect_by_server() {
+        let parser = AutoListingParser::default();
+        assert_eq!(
+            parser.detect(Some("nginx/1.24.0"), "<html></html>"),
+            Some(ParserType::Nginx)
+        );
+        assert_eq!(parser.detect(None, "<html></html>"), None);
+    }
+
+    #[test]
+    fn test_redirect() {
+        use std::io::{Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]).unwrap();
+            stream
+                .write_all(
+                    b"HTTP/1.1 301 Moved Permanently\r\n\
+                    Location: /linux/debian/dists/bookworm/index.html\r\n\
+                    Content-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .unwrap();
+        });
+        let parser = AutoListingParser::default();
+        assert!(!parser.is_auto_redirect());
+        let client = Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let url = Url::parse(&format!("http://{addr}/linux/raspbian/dists/bookworm/")).unwrap();
+        match parser.get_list(&client, &url).unwrap() {
+            ListResult::Redirect(target) => assert_eq!(target, "/linux/debian/dists/bookworm/"),
+            ListResult::List(_) => panic!("redirect is followed"),
+        }
+        server.join().unwrap();
+    }
+}
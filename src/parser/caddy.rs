@@ -1,31 +1,31 @@
 /// A parser for default caddy file_server format
-use crate::{
-    listing::{FileSize, FileType, ListItem},
-    utils::get,
-};
+use crate::listing::{FileType, ListItem};
 
 use super::*;
 use anyhow::Result;
-use chrono::NaiveDateTime;
 use scraper::{Html, Selector};
 
 #[derive(Debug, Clone, Default)]
 pub struct CaddyListingParser;
 
 impl Parser for CaddyListingParser {
-    fn get_list(&self, client: &Client, url: &Url) -> Result<ListResult> {
-        let resp = get(client, url.clone())?;
-        let url = resp.url().clone();
-        let body = resp.text()?;
-        assert_if_url_has_no_trailing_slash(&url);
-        let document = Html::parse_document(&body);
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let document = Html::parse_document(body);
         let selector = Selector::parse("tr.file").unwrap();
         let mut items = Vec::new();
         for element in document.select(&selector) {
+            let layout_error = |reason| ParseError::layout(url, reason, &element.html());
             // name and herf
             let selector = Selector::parse("td a").unwrap();
-            let a = element.select(&selector).next().unwrap();
-            let href = a.value().attr("href").unwrap();
+            let a = element
+                .select(&selector)
+                .next()
+                .ok_or_else(|| layout_error("cannot find td a"))?;
+            let href = a
+                .value()
+                .attr("href")
+                .ok_or_else(|| layout_error("cannot find href inside <a>"))?;
             // Caddy file_server will append "./" to href
             let name = get_real_name_from_href(href)
                 .trim_start_matches("./")
@@ -39,10 +39,7 @@ impl Parser for CaddyListingParser {
             // size
             let selector = Selector::parse("td.size div.sizebar div.sizebar-text").unwrap();
             let size = match element.select(&selector).next() {
-                Some(s) => {
-                    let (n_size, unit) = FileSize::get_humanized(s.inner_html().trim());
-                    Some(FileSize::HumanizedBinary(n_size, unit))
-                }
+                Some(s) => Some(parse_humanized_binary(url, s.inner_html().trim())?),
                 None => None,
             };
             // date
@@ -50,13 +47,11 @@ impl Parser for CaddyListingParser {
             let mtime = element
                 .select(&selector)
                 .next()
-                .unwrap()
-                .value()
-                .attr("datetime")
-                .unwrap()
+                .and_then(|t| t.value().attr("datetime"))
+                .ok_or_else(|| layout_error("cannot find datetime of td.timestamp time"))?
                 .trim();
             // Store UTC time
-            let date = NaiveDateTime::parse_from_str(mtime, "%Y-%m-%dT%H:%M:%S%Z")?;
+            let date = parse_date(url, mtime, &["%Y-%m-%dT%H:%M:%S%Z"])?;
 
             items.push(ListItem::new(href, name, type_, size, date))
         }
@@ -67,19 +62,17 @@ impl Parser for CaddyListingParser {
 
 #[cfg(test)]
 mod tests {
-    use crate::listing::SizeUnit;
+    use crate::listing::{FileSize, SizeUnit};
+    use chrono::NaiveDateTime;
 
     use super::*;
 
     #[test]
     fn test_sdumirror_ubuntu() {
-        let client = reqwest::blocking::Client::new();
-        let items = CaddyListingParser
-            .get_list(
-                &client,
-                &url::Url::parse("http://localhost:1921/sdumirror-ubuntu").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(
+            &CaddyListingParser,
+            "http://localhost:1921/sdumirror-ubuntu/",
+        );
         match items {
             ListResult::List(items) => {
                 assert_eq!(items.len(), 7);
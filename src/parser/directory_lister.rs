@@ -1,48 +1,63 @@
-use crate::{
-    listing::{FileSize, FileType, ListItem},
-    utils::get,
-};
+use crate::listing::{FileType, ListItem};
 
 use super::*;
 use anyhow::Result;
-use chrono::NaiveDateTime;
 use scraper::{Html, Selector};
 
 #[derive(Debug, Clone, Default)]
 pub struct DirectoryListerListingParser;
 
 impl Parser for DirectoryListerListingParser {
-    fn get_list(&self, client: &reqwest::blocking::Client, url: &url::Url) -> Result<ListResult> {
-        let resp = get(client, url.clone())?;
-        let url = resp.url().clone();
-        let body = resp.text()?;
-        assert_if_url_has_no_trailing_slash(&url);
-        let document = Html::parse_document(&body);
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let document = Html::parse_document(body);
         // https://github.com/DirectoryLister/DirectoryLister/blob/0283f14aa1fbd97796f753e8d6105c752546050f/app/views/components/file.twig
 
         // find <ul> which contains file index
         let selector = Selector::parse("ul").unwrap();
-        let indexlist = document.select(&selector).next().unwrap();
+        let indexlist = document
+            .select(&selector)
+            .next()
+            .ok_or_else(|| ParseError::layout(url, "cannot find <ul>", body))?;
         // find second <li>
         let selector = Selector::parse("li").unwrap();
-        let indexlist = indexlist.select(&selector).nth(1).unwrap();
+        let indexlist = indexlist
+            .select(&selector)
+            .nth(1)
+            .ok_or_else(|| ParseError::layout(url, "cannot find second <li>", &indexlist.html()))?;
         let selector = Selector::parse("a").unwrap();
         let mut items = Vec::new();
         for element in indexlist.select(&selector) {
-            let href = element.value().attr("href").unwrap();
+            let layout_error = |reason| ParseError::layout(url, reason, &element.html());
+            let href = element
+                .value()
+                .attr("href")
+                .ok_or_else(|| layout_error("cannot find href inside <a>"))?;
             let href = url.join(href)?;
             // displayed file name, class = "flex-1 truncate"
             let selector = Selector::parse("div.flex-1.truncate").unwrap();
-            let displayed_filename = element.select(&selector).next().unwrap().inner_html();
+            let displayed_filename = element
+                .select(&selector)
+                .next()
+                .ok_or_else(|| layout_error("cannot find file name"))?
+                .inner_html();
             let displayed_filename = displayed_filename.trim();
             // size, class = "hidden whitespace-nowrap text-right mx-2 w-1/6 sm:block"
             let selector = Selector::parse("div.hidden.whitespace-nowrap.text-right.mx-2").unwrap();
-            let size = element.select(&selector).next().unwrap().inner_html();
+            let size = element
+                .select(&selector)
+                .next()
+                .ok_or_else(|| layout_error("cannot find size"))?
+                .inner_html();
             let size = size.trim();
             // mtime, class = "hidden whitespace-nowrap text-right truncate ml-2 w-1/4 sm:block"
             let selector =
                 Selector::parse("div.hidden.whitespace-nowrap.text-right.truncate.ml-2").unwrap();
-            let mtime = element.select(&selector).next().unwrap().inner_html();
+            let mtime = element
+                .select(&selector)
+                .next()
+                .ok_or_else(|| layout_error("cannot find mtime"))?
+                .inner_html();
             let mtime = mtime.trim();
 
             if displayed_filename == ".." {
@@ -53,19 +68,17 @@ impl Parser for DirectoryListerListingParser {
             } else {
                 FileType::File
             };
-            let date = NaiveDateTime::parse_from_str(mtime, "%Y-%m-%d %H:%M:%S")?;
+            let date = parse_date(url, mtime, &["%Y-%m-%d %H:%M:%S"])?;
+            let size = if size == "—" {
+                None
+            } else {
+                Some(parse_humanized_binary(url, size)?)
+            };
             items.push(ListItem::new(
                 href,
                 displayed_filename.to_string(),
                 type_,
-                {
-                    if size == "—" {
-                        None
-                    } else {
-                        let (n_size, unit) = FileSize::get_humanized(size);
-                        Some(FileSize::HumanizedBinary(n_size, unit))
-                    }
-                },
+                size,
                 date,
             ))
         }
@@ -78,19 +91,14 @@ impl Parser for DirectoryListerListingParser {
 mod tests {
     use url::Url;
 
-    use crate::listing::SizeUnit;
+    use crate::listing::{FileSize, SizeUnit};
+    use chrono::NaiveDateTime;
 
     use super::*;
 
     #[test]
     fn test_vyos() {
-        let client = reqwest::blocking::Client::new();
-        let items = DirectoryListerListingParser
-            .get_list(
-                &client,
-                &url::Url::parse("http://localhost:1921/vyos/").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(&DirectoryListerListingParser, "http://localhost:1921/vyos/");
         match items {
             ListResult::List(items) => {
                 assert_eq!(items.len(), 7);
@@ -128,13 +136,10 @@ mod tests {
 
     #[test]
     fn test_vyos_2() {
-        let client = reqwest::blocking::Client::new();
-        let items = DirectoryListerListingParser
-            .get_list(
-                &client,
-                &url::Url::parse("http://localhost:1921/vyos/vyos-accel-ppp/").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(
+            &DirectoryListerListingParser,
+            "http://localhost:1921/vyos/vyos-accel-ppp/",
+        );
         match items {
             ListResult::List(items) => {
                 assert_eq!(items.len(), 3);
@@ -1,7 +1,4 @@
-use crate::{
-    listing::{FileSize, FileType, ListItem},
-    utils::get,
-};
+use crate::listing::{FileType, ListItem};
 use chrono::NaiveDateTime;
 use scraper::{Html, Selector};
 // use tracing::debug;
@@ -26,26 +23,38 @@ impl Default for DockerListingParser {
     }
 }
 
+/// Redirect (not followed by client) of directory, which is a symlink in Docker listing.
+pub fn redirect_of(resp: &reqwest::blocking::Response) -> Result<Option<ListResult>> {
+    let Some(url) = resp.headers().get("location") else {
+        return Ok(None);
+    };
+    let mut url = url.to_str()?.to_string();
+    // replace /index.html at the end to /
+    if url.ends_with("/index.html") {
+        url = url.trim_end_matches("/index.html").to_string();
+        url.push('/');
+    }
+    Ok(Some(ListResult::Redirect(url)))
+}
+
 impl Parser for DockerListingParser {
     fn is_auto_redirect(&self) -> bool {
         false
     }
 
-    fn get_list(&self, client: &reqwest::blocking::Client, url: &url::Url) -> Result<ListResult> {
-        assert_if_url_has_no_trailing_slash(url);
+    fn get_list(&self, client: &Client, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
         let resp = get(client, url.clone())?;
-        // if is a redirect?
-        if let Some(url) = resp.headers().get("location") {
-            let mut url = url.to_str()?.to_string();
-            // replace /index.html at the end to /
-            if url.ends_with("/index.html") {
-                url = url.trim_end_matches("/index.html").to_string();
-                url.push('/');
-            }
-            return Ok(ListResult::Redirect(url));
+        if let Some(redirect) = redirect_of(&resp)? {
+            return Ok(redirect);
         }
         let body = resp.text()?;
-        let document = Html::parse_document(&body);
+        self.parse_body(&body, url)
+    }
+
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let document = Html::parse_document(body);
         let selector = Selector::parse("a").unwrap();
         let mut items = Vec::new();
         for element in document.select(&selector) {
@@ -68,26 +77,27 @@ impl Parser for DockerListingParser {
                 } else {
                     let metadata_raw = element
                         .next_sibling()
-                        .unwrap()
-                        .value()
-                        .as_text()
-                        .unwrap()
-                        .to_string();
+                        .and_then(|n| n.value().as_text().map(|t| t.to_string()))
+                        .ok_or_else(|| {
+                            ParseError::layout(
+                                url,
+                                "cannot find metadata after <a>",
+                                &element.html(),
+                            )
+                        })?;
                     let metadata_raw = metadata_raw.trim();
-                    let metadata = self.metadata_regex.captures(metadata_raw).unwrap();
-                    let date = metadata.get(1).unwrap().as_str();
-                    let date = match NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S") {
-                        Ok(date) => date,
-                        Err(_) => NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap(),
-                    };
-                    let size = metadata.get(3).unwrap().as_str();
+                    let metadata = self.metadata_regex.captures(metadata_raw).ok_or_else(|| {
+                        ParseError::layout(url, "metadata does not match", metadata_raw)
+                    })?;
+                    let date =
+                        parse_date(url, &metadata[1], &["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"])?;
+                    let size = &metadata[3];
                     if size == "-" {
                         (FileType::Directory, None, date)
                     } else {
-                        let (n_size, unit) = FileSize::get_humanized(size);
                         (
                             FileType::File,
-                            Some(FileSize::HumanizedBinary(n_size, unit)),
+                            Some(parse_humanized_binary(url, size)?),
                             date,
                         )
                     }
@@ -105,19 +115,16 @@ impl Parser for DockerListingParser {
 
 #[cfg(test)]
 mod tests {
-    use crate::listing::SizeUnit;
+    use crate::listing::{FileSize, SizeUnit};
 
     use super::*;
 
     #[test]
     fn test_docker() {
-        let client = reqwest::blocking::Client::new();
-        let items = DockerListingParser::default()
-            .get_list(
-                &client,
-                &url::Url::parse("http://localhost:1921/docker/").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(
+            &DockerListingParser::default(),
+            "http://localhost:1921/docker/",
+        );
         match items {
             ListResult::List(items) => {
                 assert_eq!(items.len(), 45);
@@ -143,13 +150,10 @@ mod tests {
 
     #[test]
     fn test_docker_2() {
-        let client = reqwest::blocking::Client::new();
-        let items = DockerListingParser::default()
-            .get_list(
-                &client,
-                &url::Url::parse("http://localhost:1921/docker/armv7l/").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(
+            &DockerListingParser::default(),
+            "http://localhost:1921/docker/armv7l/",
+        );
         match items {
             ListResult::List(items) => {
                 assert_eq!(items.len(), 2);
@@ -1,25 +1,18 @@
 // Nginx fancyindex parser
 
-use crate::{
-    listing::{FileSize, FileType, ListItem},
-    utils::get,
-};
+use crate::listing::{FileType, ListItem};
 
 use super::*;
 use anyhow::Result;
-use chrono::NaiveDateTime;
 use scraper::{Html, Selector};
 
 #[derive(Debug, Clone, Default)]
 pub struct FancyIndexListingParser;
 
 impl Parser for FancyIndexListingParser {
-    fn get_list(&self, client: &Client, url: &Url) -> Result<ListResult> {
-        let resp = get(client, url.clone())?;
-        let url = resp.url().clone();
-        let body = resp.text()?;
-        assert_if_url_has_no_trailing_slash(&url);
-        let document = Html::parse_document(&body);
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let document = Html::parse_document(body);
         let selector = Selector::parse("tbody tr").unwrap();
         let mut items = Vec::new();
         for element in document.select(&selector) {
@@ -27,8 +20,15 @@ impl Parser for FancyIndexListingParser {
             let size_selector = Selector::parse("td.size").unwrap();
             let date_selector = Selector::parse("td.date").unwrap();
 
-            let a = element.select(&link_selector).next().unwrap();
-            let href = a.value().attr("href").unwrap();
+            let layout_error = |reason| ParseError::layout(url, reason, &element.html());
+            let a = element
+                .select(&link_selector)
+                .next()
+                .ok_or_else(|| layout_error("cannot find td.link a"))?;
+            let href = a
+                .value()
+                .attr("href")
+                .ok_or_else(|| layout_error("cannot find href inside <a>"))?;
             let displayed_filename = a.inner_html();
 
             if displayed_filename == "Parent Directory/" || href == "../" {
@@ -42,35 +42,27 @@ impl Parser for FancyIndexListingParser {
             } else {
                 FileType::File
             };
-            let size = element.select(&size_selector).next().unwrap().inner_html();
+            let size = element
+                .select(&size_selector)
+                .next()
+                .ok_or_else(|| layout_error("cannot find td.size"))?
+                .inner_html();
             let size = size.trim();
-            let date = element.select(&date_selector).next().unwrap().inner_html();
+            let date = element
+                .select(&date_selector)
+                .next()
+                .ok_or_else(|| layout_error("cannot find td.date"))?
+                .inner_html();
             let date = date.trim();
 
-            // decide which time format to use
-            let date_fmt = if date.len() == 16 {
-                "%Y-%m-%d %H:%M"
-            } else if date.len() == 19 {
-                "%Y-%m-%d %H:%M:%S"
+            let date = parse_date(url, date, &["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S"])?;
+            let size = if size == "-" {
+                None
             } else {
-                unreachable!()
+                Some(parse_humanized_binary(url, size)?)
             };
-            let date = NaiveDateTime::parse_from_str(date, date_fmt)?;
 
-            items.push(ListItem::new(
-                href,
-                name,
-                type_,
-                {
-                    if size == "-" {
-                        None
-                    } else {
-                        let (n_size, unit) = FileSize::get_humanized(size);
-                        Some(FileSize::HumanizedBinary(n_size, unit))
-                    }
-                },
-                date,
-            ));
+            items.push(ListItem::new(href, name, type_, size, date));
         }
 
         Ok(ListResult::List(items))
@@ -80,17 +72,12 @@ impl Parser for FancyIndexListingParser {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::listing::SizeUnit;
+    use crate::listing::{FileSize, SizeUnit};
+    use chrono::NaiveDateTime;
 
     #[test]
     fn test_njumirrors() {
-        let client = reqwest::blocking::Client::new();
-        let items = FancyIndexListingParser
-            .get_list(
-                &client,
-                &Url::parse("http://localhost:1921/bmclapi/").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(&FancyIndexListingParser, "http://localhost:1921/bmclapi/");
         match items {
             ListResult::List(items) => {
                 assert_eq!(items[0].name, "bouncycastle");
@@ -119,13 +106,7 @@ mod tests {
 
     #[test]
     fn test_loongnix() {
-        let client = reqwest::blocking::Client::new();
-        let items = FancyIndexListingParser
-            .get_list(
-                &client,
-                &Url::parse("http://localhost:1921/loongnix/").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(&FancyIndexListingParser, "http://localhost:1921/loongnix/");
         match items {
             ListResult::List(items) => {
                 assert_eq!(items[0].name, "contrib");
@@ -0,0 +1,191 @@
+// FTP upstream, listing with MLSD (falling back to LIST when it is not supported).
+// The reqwest client is not used here: a new FTP control connection is made for every listing.
+
+use std::time::Duration;
+
+use crate::listing::{FileSize, FileType, ListItem};
+
+use super::*;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset, Utc};
+use suppaftp::{list::ListParser, FtpStream};
+use tracing::{debug, warn};
+
+const FTP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Connect and login to FTP server of given URL. Credentials in URL are used if given.
+pub fn connect(url: &Url) -> Result<FtpStream> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| anyhow!("FTP URL without host: {}", url))?;
+    let port = url.port_or_known_default().unwrap_or(21);
+    let addr = std::net::ToSocketAddrs::to_socket_addrs(&(host, port))?
+        .next()
+        .ok_or_else(|| anyhow!("Cannot resolve {}", host))?;
+    let mut stream = FtpStream::connect_timeout(addr, FTP_TIMEOUT)?;
+    let (user, password) = if url.username().is_empty() {
+        ("anonymous".to_string(), "tsumugu@".to_string())
+    } else {
+        (
+            decode_path(url.username()),
+            decode_path(url.password().unwrap_or_default()),
+        )
+    };
+    stream.login(user, password)?;
+    stream.transfer_type(suppaftp::types::FileType::Binary)?;
+    Ok(stream)
+}
+
+/// Percent-decode path (or credentials) in URL for FTP commands.
+pub fn decode_path(s: &str) -> String {
+    percent_encoding::percent_decode_str(s)
+        .decode_utf8_lossy()
+        .to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ListFormat {
+    Mlsd,
+    List,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FtpListingParser;
+
+impl FtpListingParser {
+    fn parse_lines(&self, lines: &[String], url: &Url, format: ListFormat) -> Vec<ListItem> {
+        let mut items = Vec::new();
+        for line in lines {
+            let file = match format {
+                ListFormat::Mlsd => ListParser::parse_mlsd(line),
+                ListFormat::List => {
+                    ListParser::parse_posix(line).or_else(|_| ListParser::parse_dos(line))
+                }
+            };
+            let file = match file {
+                Ok(file) => file,
+                Err(e) => {
+                    // Lines like "total 123" in LIST output
+                    debug!("Skipping unparsable line {:?}: {:?}", line, e);
+                    continue;
+                }
+            };
+            let name = file.name().to_string();
+            if name == "." || name == ".." {
+                continue;
+            }
+            let type_ = if file.is_directory() {
+                FileType::Directory
+            } else if file.is_file() {
+                FileType::File
+            } else {
+                warn!("Skipping symlink {} in {}", name, url);
+                continue;
+            };
+            let href = join_name(url, &name, type_);
+            let size = match type_ {
+                FileType::Directory => None,
+                FileType::File => Some(FileSize::Precise(file.size() as u64)),
+            };
+            let mtime = DateTime::<Utc>::from(file.modified()).naive_utc();
+            let item = ListItem::new(href, name, type_, size, mtime);
+            // MLSD "modify" fact is always in UTC, while LIST shows server local time
+            items.push(match format {
+                ListFormat::Mlsd => item.with_timezone(FixedOffset::east_opt(0).unwrap()),
+                ListFormat::List => item,
+            });
+        }
+        items
+    }
+}
+
+impl Parser for FtpListingParser {
+    fn get_list(&self, _client: &Client, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let mut stream = connect(url)?;
+        let path = decode_path(url.path());
+        let (lines, format) = match stream.mlsd(Some(&path)) {
+            Ok(lines) => (lines, ListFormat::Mlsd),
+            Err(e) => {
+                debug!("MLSD failed ({:?}), falling back to LIST", e);
+                (stream.list(Some(&path))?, ListFormat::List)
+            }
+        };
+        let _ = stream.quit();
+        Ok(ListResult::List(self.parse_lines(&lines, url, format)))
+    }
+
+    /// Parse saved output of MLSD or LIST.
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let lines: Vec<String> = body.lines().map(|l| l.to_string()).collect();
+        // MLSD lines always start with facts like "type=file;"
+        let format = if lines.iter().any(|l| l.starts_with("type=")) {
+            ListFormat::Mlsd
+        } else {
+            ListFormat::List
+        };
+        Ok(ListResult::List(self.parse_lines(&lines, url, format)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    #[test]
+    fn test_mlsd() {
+        let lines: Vec<String> = [
+            "type=cdir;modify=20240310044524;UNIX.mode=0755; .",
+            "type=pdir;modify=20240310044524;UNIX.mode=0755; ..",
+            "type=dir;modify=20231107123900;UNIX.mode=0755; dists",
+            "type=file;size=111472;modify=20231107123900;UNIX.mode=0644; Packages",
+            "type=file;size=5231;modify=20230804102400;UNIX.mode=0644; ceph-base_17.2.6-pve1+3.changelog",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let url = Url::parse("ftp://localhost/debian/").unwrap();
+        let items = FtpListingParser.parse_lines(&lines, &url, ListFormat::Mlsd);
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].name, "dists");
+        assert_eq!(items[0].type_, FileType::Directory);
+        assert_eq!(
+            items[0].url,
+            Url::parse("ftp://localhost/debian/dists/").unwrap()
+        );
+        assert_eq!(items[1].name, "Packages");
+        assert_eq!(items[1].size, Some(FileSize::Precise(111472)));
+        assert_eq!(
+            items[1].mtime,
+            NaiveDateTime::parse_from_str("2023-11-07 12:39:00", "%Y-%m-%d %H:%M:%S").unwrap()
+        );
+        assert_eq!(items[1].timezone, FixedOffset::east_opt(0));
+        assert_eq!(
+            items[2].url,
+            Url::parse("ftp://localhost/debian/ceph-base_17.2.6-pve1+3.changelog").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        let lines: Vec<String> = [
+            "total 3",
+            "drwxr-xr-x    2 ftp      ftp          4096 Nov 07  2023 dists",
+            "-rw-r--r--    1 ftp      ftp        111472 Nov 07  2023 Packages",
+            "lrwxrwxrwx    1 ftp      ftp             5 Nov 07  2023 stable -> dists",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let url = Url::parse("ftp://localhost/debian/").unwrap();
+        let items = FtpListingParser.parse_lines(&lines, &url, ListFormat::List);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].type_, FileType::Directory);
+        assert_eq!(items[1].name, "Packages");
+        assert_eq!(items[1].size, Some(FileSize::Precise(111472)));
+        assert_eq!(items[1].timezone, None);
+    }
+}
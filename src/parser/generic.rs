@@ -0,0 +1,373 @@
+// A generic parser driven by a YAML description of the listing layout.
+// Useful for one-off upstreams which differ from built-in parsers only in small details.
+
+use std::path::Path;
+
+use crate::listing::{FileSize, FileType, ListItem};
+
+use super::*;
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use scraper::{ElementRef, Html, Selector};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeConvention {
+    /// 1024B -> 1K
+    #[default]
+    Binary,
+    /// 1000B -> 1K
+    Decimal,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NameSource {
+    /// Get name from (url-decoded) href, which is not affected by truncation
+    #[default]
+    Href,
+    /// Get name from displayed text of link
+    Text,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DirectoryRules {
+    /// Treat href ending with "/" as directory.
+    #[serde(default = "default_true")]
+    pub trailing_slash: bool,
+    /// Treat row as directory if text of size cell is one of these.
+    #[serde(default)]
+    pub size_markers: Vec<String>,
+    /// Treat row as directory if this selector matches inside the row.
+    #[serde(default)]
+    pub selector: Option<String>,
+}
+
+impl Default for DirectoryRules {
+    fn default() -> Self {
+        Self {
+            trailing_slash: true,
+            size_markers: vec![],
+            selector: None,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_skip() -> Vec<String> {
+    vec!["..".to_string(), "Parent Directory".to_string()]
+}
+
+fn default_empty_markers() -> Vec<String> {
+    vec!["-".to_string(), "".to_string()]
+}
+
+/// Description of a listing layout, deserialized from YAML.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenericConfig {
+    /// Selector of every row of the listing.
+    pub rows: String,
+    /// Selector of <a> inside the row.
+    #[serde(default = "default_link")]
+    pub link: String,
+    #[serde(default)]
+    pub name_from: NameSource,
+    /// Selector of size cell inside the row.
+    #[serde(default)]
+    pub size: Option<String>,
+    /// Selector of mtime cell inside the row.
+    /// Required, as files without mtime would be downloaded again in every sync.
+    pub mtime: String,
+    /// Read mtime from this attribute of mtime cell, instead of its text.
+    #[serde(default)]
+    pub mtime_attr: Option<String>,
+    /// chrono formats to try in order.
+    #[serde(default)]
+    pub mtime_formats: Vec<String>,
+    #[serde(default)]
+    pub size_unit: SizeConvention,
+    /// Size texts meaning "no size".
+    #[serde(default = "default_empty_markers")]
+    pub size_empty_markers: Vec<String>,
+    #[serde(default)]
+    pub directory: DirectoryRules,
+    /// Displayed names (or names from href) to skip.
+    #[serde(default = "default_skip")]
+    pub skip: Vec<String>,
+}
+
+fn default_link() -> String {
+    "a".to_string()
+}
+
+#[derive(Debug, Clone)]
+pub struct GenericListingParser {
+    config: GenericConfig,
+    rows: Selector,
+    link: Selector,
+    size: Option<Selector>,
+    mtime: Selector,
+    directory: Option<Selector>,
+}
+
+fn parse_selector(s: &str) -> Result<Selector> {
+    Selector::parse(s).map_err(|e| anyhow!("Invalid selector {:?}: {:?}", s, e))
+}
+
+fn element_text(element: ElementRef) -> String {
+    element.text().collect::<String>().trim().to_string()
+}
+
+impl GenericListingParser {
+    pub fn new(config: GenericConfig) -> Result<Self> {
+        if config.mtime_formats.is_empty() {
+            return Err(anyhow!("mtime_formats is required"));
+        }
+        Ok(Self {
+            rows: parse_selector(&config.rows)?,
+            link: parse_selector(&config.link)?,
+            size: config.size.as_deref().map(parse_selector).transpose()?,
+            mtime: parse_selector(&config.mtime)?,
+            directory: config
+                .directory
+                .selector
+                .as_deref()
+                .map(parse_selector)
+                .transpose()?,
+            config,
+        })
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        Self::new(serde_yaml::from_str(&data)?)
+    }
+
+    fn parse_mtime(&self, url: &Url, s: &str) -> Result<NaiveDateTime, ParseError> {
+        for fmt in &self.config.mtime_formats {
+            if let Ok(date) = NaiveDateTime::parse_from_str(s, fmt) {
+                return Ok(date);
+            }
+        }
+        Err(ParseError::date(url, s))
+    }
+
+    fn parse_size(&self, url: &Url, s: &str) -> Result<Option<FileSize>, ParseError> {
+        if self.config.size_empty_markers.iter().any(|m| m == s) {
+            return Ok(None);
+        }
+        if let Ok(size) = s.parse::<u64>() {
+            return Ok(Some(FileSize::Precise(size)));
+        }
+        let (n_size, unit) = FileSize::get_humanized(s).ok_or_else(|| ParseError::size(url, s))?;
+        Ok(Some(match self.config.size_unit {
+            SizeConvention::Binary => FileSize::HumanizedBinary(n_size, unit),
+            SizeConvention::Decimal => FileSize::HumanizedDecimal(n_size, unit),
+        }))
+    }
+}
+
+impl Parser for GenericListingParser {
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let document = Html::parse_document(body);
+        let mut items = Vec::new();
+        for element in document.select(&self.rows) {
+            let a = match element.select(&self.link).next() {
+                Some(a) => a,
+                // rows like table header
+                None => continue,
+            };
+            let href = match a.value().attr("href") {
+                Some(href) => href,
+                None => continue,
+            };
+            let displayed_name = element_text(a);
+            let name = match self.config.name_from {
+                NameSource::Href => get_real_name_from_href(href),
+                NameSource::Text => displayed_name.trim_end_matches('/').to_string(),
+            };
+            if href == "../"
+                || self
+                    .config
+                    .skip
+                    .iter()
+                    .any(|s| *s == name || *s == displayed_name)
+            {
+                continue;
+            }
+            let mut href = url.join(href)?;
+
+            let size = match &self.size {
+                Some(selector) => element.select(selector).next().map(element_text),
+                None => None,
+            };
+            let mtime = element
+                .select(&self.mtime)
+                .next()
+                .map(|e| match &self.config.mtime_attr {
+                    Some(attr) => e.value().attr(attr).unwrap_or_default().trim().to_string(),
+                    None => element_text(e),
+                });
+
+            let rules = &self.config.directory;
+            let is_dir = (rules.trailing_slash && href.path().ends_with('/'))
+                || size
+                    .as_ref()
+                    .map(|s| rules.size_markers.contains(s))
+                    .unwrap_or(false)
+                || self
+                    .directory
+                    .as_ref()
+                    .map(|s| element.select(s).next().is_some())
+                    .unwrap_or(false);
+            let type_ = if is_dir {
+                FileType::Directory
+            } else {
+                FileType::File
+            };
+            if type_ == FileType::Directory && !href.path().ends_with('/') {
+                href.set_path(&format!("{}/", href.path()));
+            }
+
+            let size = match type_ {
+                FileType::Directory => None,
+                FileType::File => match size {
+                    Some(s) => self.parse_size(url, &s)?,
+                    None => None,
+                },
+            };
+            let date = match mtime {
+                // Directories in some listings do not show mtime
+                Some(mtime) if type_ == FileType::Directory && mtime == "-" => {
+                    NaiveDateTime::default()
+                }
+                Some(mtime) => self.parse_mtime(url, &mtime)?,
+                None if type_ == FileType::Directory => NaiveDateTime::default(),
+                None => {
+                    return Err(ParseError::layout(url, "no mtime of file", &element.html()).into())
+                }
+            };
+
+            items.push(ListItem::new(href, name, type_, size, date))
+        }
+
+        Ok(ListResult::List(items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::listing::SizeUnit;
+
+    use super::*;
+
+    fn build(config: &str) -> GenericListingParser {
+        GenericListingParser::new(serde_yaml::from_str(config).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_generic_fancyindex() {
+        let parser = build(
+            r#"
+rows: "tbody tr"
+link: "td.link a"
+size: "td.size"
+mtime: "td.date"
+mtime_formats: ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+skip: ["Parent directory"]
+"#,
+        );
+        let items = parse_fixture(&parser, "http://localhost:1921/bmclapi/");
+        match items {
+            ListResult::List(items) => {
+                assert_eq!(items[0].name, "bouncycastle");
+                assert_eq!(items[0].type_, FileType::Directory);
+                assert_eq!(items[0].size, None);
+                assert_eq!(
+                    items[0].mtime,
+                    NaiveDateTime::parse_from_str("2024-04-23 19:01:54", "%Y-%m-%d %H:%M:%S")
+                        .unwrap()
+                );
+                assert_eq!(items[items.len() - 1].name, "lwjgURL");
+                assert_eq!(items[items.len() - 1].type_, FileType::File);
+                // sizes without unit are taken as precise
+                assert_eq!(items[items.len() - 1].size, Some(FileSize::Precise(1767)));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_generic_lighttpd() {
+        let parser = build(
+            r#"
+rows: "tbody tr"
+size: ".s"
+mtime: ".m"
+mtime_formats: ["%Y-%b-%d %H:%M:%S"]
+size_unit: decimal
+directory:
+  size_markers: ["-"]
+"#,
+        );
+        let items = parse_fixture(&parser, "http://localhost:1921/buildroot/acl/");
+        match items {
+            ListResult::List(items) => {
+                assert_eq!(items.len(), 4);
+                assert_eq!(items[0].name, "acl-2.2.52.src.tar.gz");
+                assert_eq!(items[0].type_, FileType::File);
+                assert_eq!(
+                    items[0].size,
+                    Some(FileSize::HumanizedDecimal(377.5, SizeUnit::K))
+                );
+                assert_eq!(
+                    items[0].mtime,
+                    NaiveDateTime::parse_from_str("2013-05-19 06:10:38", "%Y-%m-%d %H:%M:%S")
+                        .unwrap()
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_generic_config_error() {
+        assert!(serde_yaml::from_str::<GenericConfig>("rows: tr\nunknown: 1").is_err());
+        // Files without mtime would never be taken as up-to-date
+        assert!(serde_yaml::from_str::<GenericConfig>("rows: tr\nsize: td").is_err());
+        let config: GenericConfig = serde_yaml::from_str("rows: tr\nmtime: td").unwrap();
+        assert!(GenericListingParser::new(config).is_err());
+    }
+
+    #[test]
+    fn test_generic_missing_mtime() {
+        let parser = build(
+            r#"
+rows: "tr"
+mtime: "td.date"
+mtime_formats: ["%Y-%m-%d %H:%M"]
+"#,
+        );
+        let url = Url::parse("http://localhost/").unwrap();
+        let body = r#"<table>
+<tr><td><a href="sub/">sub/</a></td></tr>
+<tr><td><a href="a.txt">a.txt</a></td><td class="date">2024-01-02 03:04</td></tr>
+</table>"#;
+        match parser.parse_body(body, &url).unwrap() {
+            ListResult::List(items) => {
+                assert_eq!(items.len(), 2);
+                assert_eq!(items[0].mtime, NaiveDateTime::default());
+            }
+            _ => unreachable!(),
+        }
+        let body = r#"<table><tr><td><a href="b.txt">b.txt</a></td></tr></table>"#;
+        assert!(parser.parse_body(body, &url).is_err());
+    }
+}
@@ -1,44 +1,38 @@
-use crate::{
-    listing::{FileSize, FileType, ListItem},
-    utils::get,
-};
-use chrono::NaiveDateTime;
+use crate::listing::{FileType, ListItem};
 use scraper::{Html, Selector};
 // use tracing::debug;
 
 use super::*;
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 
 #[derive(Debug, Clone, Default)]
 pub struct LighttpdListingParser;
 
 impl Parser for LighttpdListingParser {
-    fn get_list(&self, client: &Client, url: &Url) -> Result<ListResult> {
-        let resp = get(client, url.clone())?;
-        let url = resp.url().clone();
-        let body = resp.text()?;
-        assert_if_url_has_no_trailing_slash(&url);
-        let document = Html::parse_document(&body);
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let document = Html::parse_document(body);
         let selector = Selector::parse("tbody").unwrap();
         let indexlist = document
             .select(&selector)
             .next()
-            .ok_or_else(|| anyhow!("Cannot find <tbody>"))?;
+            .ok_or_else(|| ParseError::layout(url, "cannot find <tbody>", body))?;
         let selector = Selector::parse("tr").unwrap();
         let mut items = Vec::new();
         for element in indexlist.select(&selector) {
+            let layout_error = |reason| ParseError::layout(url, reason, &element.html());
             let a = element
                 .select(&Selector::parse("a").unwrap())
                 .next()
-                .ok_or_else(|| anyhow!("Cannot find <a>"))?;
+                .ok_or_else(|| layout_error("cannot find <a>"))?;
             let mtime = element
                 .select(&Selector::parse(".m").unwrap())
                 .next()
-                .ok_or_else(|| anyhow!("Cannot find .m"))?;
+                .ok_or_else(|| layout_error("cannot find .m"))?;
             let size = element
                 .select(&Selector::parse(".s").unwrap())
                 .next()
-                .ok_or_else(|| anyhow!("Cannot find .s"))?;
+                .ok_or_else(|| layout_error("cannot find .s"))?;
 
             // let filetype = element.select(&Selector::parse(".t").unwrap()).next().unwrap();
 
@@ -49,7 +43,7 @@ impl Parser for LighttpdListingParser {
             let href = a
                 .value()
                 .attr("href")
-                .ok_or_else(|| anyhow!("Cannot find href inside <a>"))?;
+                .ok_or_else(|| layout_error("cannot find href inside <a>"))?;
             let name = get_real_name_from_href(href);
             let href = url.join(href)?;
 
@@ -61,7 +55,7 @@ impl Parser for LighttpdListingParser {
 
             let mtime = mtime.inner_html();
             let mtime = mtime.trim();
-            let mtime = NaiveDateTime::parse_from_str(mtime, "%Y-%b-%d %H:%M:%S")?;
+            let mtime = parse_date(url, mtime, &["%Y-%b-%d %H:%M:%S"])?;
 
             let size = size.inner_html();
             // Currently we just use simple replace to handle HTML entities
@@ -72,8 +66,7 @@ impl Parser for LighttpdListingParser {
             let size = if size == "-" {
                 None
             } else {
-                let (n_size, unit) = FileSize::get_humanized(size);
-                Some(FileSize::HumanizedBinary(n_size, unit))
+                Some(parse_humanized_binary(url, size)?)
             };
 
             // debug!("{} {} {} {:?} {:?}", href, name, mtime, size, type_);
@@ -86,19 +79,14 @@ impl Parser for LighttpdListingParser {
 
 #[cfg(test)]
 mod tests {
-    use crate::listing::SizeUnit;
+    use crate::listing::{FileSize, SizeUnit};
+    use chrono::NaiveDateTime;
 
     use super::*;
 
     #[test]
     fn test_buildroot_root() {
-        let client = reqwest::blocking::Client::new();
-        let items = LighttpdListingParser::default()
-            .get_list(
-                &client,
-                &Url::parse("http://localhost:1921/buildroot/").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(&LighttpdListingParser, "http://localhost:1921/buildroot/");
         match items {
             ListResult::List(items) => {
                 assert_eq!(items[0].name, "18xx-ti-utils");
@@ -128,13 +116,10 @@ mod tests {
 
     #[test]
     fn test_buildroot_subfolder() {
-        let client = reqwest::blocking::Client::new();
-        let items = LighttpdListingParser::default()
-            .get_list(
-                &client,
-                &Url::parse("http://localhost:1921/buildroot/acl/").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(
+            &LighttpdListingParser,
+            "http://localhost:1921/buildroot/acl/",
+        );
         match items {
             ListResult::List(items) => {
                 assert_eq!(items.len(), 4);
@@ -1,18 +1,100 @@
-use anyhow::Result;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
 use clap::ValueEnum;
 use reqwest::blocking::Client;
 use tracing::warn;
 use url::Url;
 
-use crate::listing::ListItem;
+use crate::{
+    listing::{FileSize, FileType, ListItem},
+    utils::get,
+};
 
 pub mod apache_f2;
+pub mod auto;
 pub mod caddy;
 pub mod directory_lister;
 pub mod docker;
 pub mod fancyindex;
+pub mod ftp;
+pub mod generic;
 pub mod lighttpd;
 pub mod nginx;
+// Nginx autoindex in JSON or XML gives precise sizes and mtimes in UTC,
+// so no fuzzy compare and timezone guessing are needed.
+pub mod nginx_json;
+pub mod nginx_xml;
+pub mod pypi;
+pub mod s3;
+
+/// Error when the listing could not be parsed as expected.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The listing does not look like what the parser expects.
+    UnexpectedLayout {
+        url: String,
+        reason: String,
+        snippet: String,
+    },
+    BadDate {
+        url: String,
+        snippet: String,
+    },
+    BadSize {
+        url: String,
+        snippet: String,
+    },
+}
+
+/// Keep snippet in error message short.
+fn truncate_snippet(snippet: &str) -> String {
+    const MAX_LEN: usize = 200;
+    match snippet.char_indices().nth(MAX_LEN) {
+        Some((idx, _)) => format!("{}...", &snippet[..idx]),
+        None => snippet.to_string(),
+    }
+}
+
+impl ParseError {
+    pub fn layout(url: &Url, reason: &str, snippet: &str) -> Self {
+        Self::UnexpectedLayout {
+            url: url.to_string(),
+            reason: reason.to_string(),
+            snippet: truncate_snippet(snippet),
+        }
+    }
+
+    pub fn date(url: &Url, snippet: &str) -> Self {
+        Self::BadDate {
+            url: url.to_string(),
+            snippet: truncate_snippet(snippet),
+        }
+    }
+
+    pub fn size(url: &Url, snippet: &str) -> Self {
+        Self::BadSize {
+            url: url.to_string(),
+            snippet: truncate_snippet(snippet),
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedLayout {
+                url,
+                reason,
+                snippet,
+            } => write!(f, "Unexpected layout in {url} ({reason}): {snippet:?}"),
+            Self::BadDate { url, snippet } => write!(f, "Bad date in {url}: {snippet:?}"),
+            Self::BadSize { url, snippet } => write!(f, "Bad size in {url}: {snippet:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
 
 #[derive(Debug)]
 pub enum ListResult {
@@ -21,14 +103,28 @@ pub enum ListResult {
 }
 
 pub trait Parser: Sync {
-    fn get_list(&self, client: &Client, url: &Url) -> Result<ListResult>;
+    /// Fetch the listing of url and parse it.
+    fn get_list(&self, client: &Client, url: &Url) -> Result<ListResult> {
+        let resp = get(client, url.clone())?;
+        let url = resp.url().clone();
+        let body = resp.text()?;
+        self.parse_body(&body, &url)
+    }
+    /// Parse a fetched listing. url is where the body comes from (after redirection).
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult>;
     fn is_auto_redirect(&self) -> bool {
         true
     }
+    /// Page to write as index.html of local directory, for listings which clients use directly (like PyPI simple index).
+    fn local_index(&self, _items: &[ListItem]) -> Option<String> {
+        None
+    }
 }
 
-#[derive(ValueEnum, Clone, Debug)]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
 pub enum ParserType {
+    /// Detect parser by fingerprinting listing of every directory
+    Auto,
     Nginx,
     ApacheF2,
     Docker,
@@ -36,11 +132,24 @@ pub enum ParserType {
     Lighttpd,
     Caddy,
     FancyIndex,
+    /// Nginx autoindex with `autoindex_format json;`
+    NginxJson,
+    /// Nginx autoindex with `autoindex_format xml;`
+    NginxXml,
+    /// FTP server, listing with MLSD or LIST
+    Ftp,
+    /// S3 compatible bucket listing (ListObjectsV2)
+    S3,
+    /// PyPI simple index (HTML or JSON), writing a local index for pip with --local-index
+    Pypi,
+    /// Parse by a YAML description given in --parser-config
+    Generic,
 }
 
 impl ParserType {
-    pub fn build(&self) -> Box<dyn Parser> {
-        match self {
+    pub fn build(&self, config: Option<&Path>) -> Result<Box<dyn Parser>> {
+        Ok(match self {
+            Self::Auto => Box::<auto::AutoListingParser>::default(),
             Self::Nginx => Box::<nginx::NginxListingParser>::default(),
             Self::ApacheF2 => Box::<apache_f2::ApacheF2ListingParser>::default(),
             Self::Docker => Box::<docker::DockerListingParser>::default(),
@@ -51,15 +160,30 @@ impl ParserType {
             Self::Lighttpd => Box::<lighttpd::LighttpdListingParser>::default(),
             Self::Caddy => Box::<caddy::CaddyListingParser>::default(),
             Self::FancyIndex => Box::<fancyindex::FancyIndexListingParser>::default(),
-        }
+            Self::NginxJson => Box::<nginx_json::NginxJsonListingParser>::default(),
+            Self::NginxXml => Box::<nginx_xml::NginxXmlListingParser>::default(),
+            Self::Ftp => Box::<ftp::FtpListingParser>::default(),
+            Self::S3 => Box::<s3::S3ListingParser>::default(),
+            Self::Pypi => Box::<pypi::PypiListingParser>::default(),
+            Self::Generic => {
+                let config = config
+                    .ok_or_else(|| anyhow!("--parser-config is required by generic parser"))?;
+                Box::new(generic::GenericListingParser::from_path(config)?)
+            }
+        })
     }
 }
 
-fn assert_if_url_has_no_trailing_slash(url: &Url) {
-    assert!(
-        url.path().ends_with('/'),
-        "URL for listing should have a trailing slash"
-    );
+fn check_trailing_slash(url: &Url) -> Result<(), ParseError> {
+    if url.path().ends_with('/') {
+        Ok(())
+    } else {
+        Err(ParseError::layout(
+            url,
+            "URL for listing should have a trailing slash",
+            url.path(),
+        ))
+    }
 }
 
 fn get_real_name_from_href(href: &str) -> String {
@@ -68,3 +192,69 @@ fn get_real_name_from_href(href: &str) -> String {
         .collect();
     name.trim_end_matches('/').to_string()
 }
+
+/// Get URL of an entry by its (unescaped) name, for listing formats without href.
+fn join_name(url: &Url, name: &str, type_: FileType) -> Url {
+    let mut href = url.clone();
+    {
+        let mut segments = href.path_segments_mut().unwrap();
+        segments.pop_if_empty().push(name);
+        if type_ == FileType::Directory {
+            segments.push("");
+        }
+    }
+    href
+}
+
+/// Parse humanized size (like "1.5K") with binary units, which most listings use.
+fn parse_humanized_binary(url: &Url, size: &str) -> Result<FileSize, ParseError> {
+    let (n_size, unit) =
+        FileSize::get_humanized(size).ok_or_else(|| ParseError::size(url, size))?;
+    Ok(FileSize::HumanizedBinary(n_size, unit))
+}
+
+/// Parse date with any of given formats.
+fn parse_date(url: &Url, date: &str, fmts: &[&str]) -> Result<chrono::NaiveDateTime, ParseError> {
+    fmts.iter()
+        .find_map(|fmt| chrono::NaiveDateTime::parse_from_str(date, fmt).ok())
+        .ok_or_else(|| ParseError::date(url, date))
+}
+
+/// Parse fixtures/<path of url>/index.html as the listing of url.
+#[cfg(test)]
+fn parse_fixture(parser: &dyn Parser, url: &str) -> ListResult {
+    let url = Url::parse(url).unwrap();
+    let path = format!(
+        "{}/fixtures{}index.html",
+        env!("CARGO_MANIFEST_DIR"),
+        url.path()
+    );
+    let body = std::fs::read_to_string(path).unwrap();
+    parser.parse_body(&body, &url).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error() {
+        let url = Url::parse("http://localhost/").unwrap();
+        assert!(matches!(
+            parse_humanized_binary(&url, "1.5Q"),
+            Err(ParseError::BadSize { .. })
+        ));
+        assert!(matches!(
+            parse_date(&url, "yesterday", &["%Y-%m-%d %H:%M"]),
+            Err(ParseError::BadDate { .. })
+        ));
+        let e = ParseError::layout(&url, "no rows", &"x".repeat(1000));
+        assert!(e.to_string().len() < 300);
+        let url = Url::parse("http://localhost/debian").unwrap();
+        assert!(nginx::NginxListingParser::default()
+            .parse_body("<html></html>", &url)
+            .unwrap_err()
+            .downcast_ref::<ParseError>()
+            .is_some());
+    }
+}
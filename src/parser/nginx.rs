@@ -1,9 +1,5 @@
 /// A parser both suitable for default nginx autoindex and apache f1 format.
-use crate::{
-    listing::{FileSize, FileType, ListItem},
-    utils::get,
-};
-use chrono::NaiveDateTime;
+use crate::listing::{FileSize, FileType, ListItem};
 use scraper::{Html, Selector};
 use tracing::debug;
 
@@ -26,12 +22,9 @@ impl Default for NginxListingParser {
 }
 
 impl Parser for NginxListingParser {
-    fn get_list(&self, client: &reqwest::blocking::Client, url: &url::Url) -> Result<ListResult> {
-        let resp = get(client, url.clone())?;
-        let url = resp.url().clone();
-        let body = resp.text()?;
-        assert_if_url_has_no_trailing_slash(&url);
-        let document = Html::parse_document(&body);
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let document = Html::parse_document(body);
         let selector = Selector::parse("a").unwrap();
         let mut items = Vec::new();
         for element in document.select(&selector) {
@@ -62,35 +55,31 @@ impl Parser for NginxListingParser {
             };
             let metadata_raw = element
                 .next_sibling()
-                .unwrap()
-                .value()
-                .as_text()
-                .unwrap()
-                .to_string();
+                .and_then(|n| n.value().as_text().map(|t| t.to_string()))
+                .ok_or_else(|| {
+                    ParseError::layout(url, "cannot find metadata after <a>", &element.html())
+                })?;
             let metadata_raw = metadata_raw.trim();
             debug!("{:?}", metadata_raw);
-            let metadata = self.metadata_regex.captures(metadata_raw).unwrap();
-            let date = metadata.get(1).unwrap().as_str();
-            let date = NaiveDateTime::parse_from_str(date, "%d-%b-%Y %H:%M")?;
-            let size = metadata.get(2).unwrap().as_str();
+            let metadata = self
+                .metadata_regex
+                .captures(metadata_raw)
+                .ok_or_else(|| ParseError::layout(url, "metadata does not match", metadata_raw))?;
+            let date = &metadata[1];
+            let date = parse_date(url, date, &["%d-%b-%Y %H:%M"])?;
+            let size = &metadata[2];
             debug!("{} {} {:?} {} {:?}", href, name, type_, date, size);
-            items.push(ListItem::new(
-                href,
-                name.to_string(),
-                type_,
-                {
-                    if size == "-" {
-                        None
-                    } else if size.contains('k') || size.contains('M') || size.contains('G') {
-                        let (n_size, unit) = FileSize::get_humanized(size);
-                        Some(FileSize::HumanizedBinary(n_size, unit))
-                    } else {
-                        let n_size = size.parse::<u64>().unwrap();
-                        Some(FileSize::Precise(n_size))
-                    }
-                },
-                date,
-            ))
+            let size = if size == "-" {
+                None
+            } else if size.contains('k') || size.contains('M') || size.contains('G') {
+                Some(parse_humanized_binary(url, size)?)
+            } else {
+                let n_size = size
+                    .parse::<u64>()
+                    .map_err(|_| ParseError::size(url, size))?;
+                Some(FileSize::Precise(n_size))
+            };
+            items.push(ListItem::new(href, name.to_string(), type_, size, date))
         }
         Ok(ListResult::List(items))
     }
@@ -98,19 +87,17 @@ impl Parser for NginxListingParser {
 
 #[cfg(test)]
 mod tests {
+    use chrono::NaiveDateTime;
     use url::Url;
 
     use super::*;
 
     #[test]
     fn test_monitoring_plugins() {
-        let client = reqwest::blocking::Client::new();
-        let items = NginxListingParser::default()
-            .get_list(
-                &client,
-                &url::Url::parse("http://localhost:1921/monitoring-plugins").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(
+            &NginxListingParser::default(),
+            "http://localhost:1921/monitoring-plugins/",
+        );
         match items {
             ListResult::List(items) => {
                 assert_eq!(items.len(), 23);
@@ -135,13 +122,10 @@ mod tests {
 
     #[test]
     fn test_proxmox() {
-        let client = reqwest::blocking::Client::new();
-        let items = NginxListingParser::default()
-            .get_list(
-                &client,
-                &url::Url::parse("http://localhost:1921/proxmox").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(
+            &NginxListingParser::default(),
+            "http://localhost:1921/proxmox/",
+        );
         match items {
             ListResult::List(items) => {
                 let target = "ceph-immutable-object-cache_17.2.6-pve1+3_amd64.deb";
@@ -157,13 +141,10 @@ mod tests {
 
     #[test]
     fn test_mysql() {
-        let client = reqwest::blocking::Client::new();
-        let items = NginxListingParser::default()
-            .get_list(
-                &client,
-                &url::Url::parse("http://localhost:1921/mysql").unwrap(),
-            )
-            .unwrap();
+        let items = parse_fixture(
+            &NginxListingParser::default(),
+            "http://localhost:1921/mysql/",
+        );
         match items {
             ListResult::List(items) => {
                 let target = "mysql-connector-c++";
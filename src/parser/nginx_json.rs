@@ -0,0 +1,93 @@
+// Nginx autoindex with `autoindex_format json;`, mtimes in RFC 1123
+
+use crate::listing::{FileSize, FileType, ListItem};
+
+use super::*;
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+use tracing::debug;
+
+#[derive(Debug, Deserialize)]
+struct NginxJsonEntry {
+    name: String,
+    #[serde(rename = "type")]
+    type_: String,
+    mtime: String,
+    size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NginxJsonListingParser;
+
+impl Parser for NginxJsonListingParser {
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let entries: Vec<NginxJsonEntry> = serde_json::from_str(body)
+            .map_err(|e| ParseError::layout(url, &e.to_string(), body))?;
+        let mut items = Vec::new();
+        for entry in entries {
+            let type_ = match entry.type_.as_str() {
+                "directory" => FileType::Directory,
+                "file" => FileType::File,
+                _ => {
+                    debug!("Skipping {} with type {}", entry.name, entry.type_);
+                    continue;
+                }
+            };
+            let href = join_name(url, &entry.name, type_);
+            let mtime = DateTime::parse_from_rfc2822(&entry.mtime)
+                .map_err(|_| ParseError::date(url, &entry.mtime))?
+                .naive_utc();
+            let size = match type_ {
+                FileType::Directory => None,
+                FileType::File => entry.size.map(FileSize::Precise),
+            };
+            items.push(
+                ListItem::new(href, entry.name, type_, size, mtime)
+                    .with_timezone(FixedOffset::east_opt(0).unwrap()),
+            );
+        }
+        Ok(ListResult::List(items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    #[test]
+    fn test_nginx_json() {
+        let items = parse_fixture(&NginxJsonListingParser, "http://localhost:1921/nginx-json/");
+        match items {
+            ListResult::List(items) => {
+                assert_eq!(items.len(), 5);
+                assert_eq!(items[0].name, "archive");
+                assert_eq!(items[0].type_, FileType::Directory);
+                assert_eq!(items[0].size, None);
+                assert_eq!(
+                    items[0].url,
+                    Url::parse("http://localhost:1921/nginx-json/archive/").unwrap()
+                );
+                assert_eq!(items[2].name, "ceph-base_17.2.6-pve1+3_amd64.deb");
+                assert_eq!(items[2].type_, FileType::File);
+                assert_eq!(items[2].size, Some(FileSize::Precise(6326220)));
+                assert_eq!(
+                    items[2].mtime,
+                    NaiveDateTime::parse_from_str("2023-05-03 15:52:37", "%Y-%m-%d %H:%M:%S")
+                        .unwrap()
+                );
+                assert_eq!(items[2].timezone, FixedOffset::east_opt(0));
+                assert_eq!(items[4].name, "release notes #1.txt");
+                assert_eq!(
+                    items[4].url,
+                    Url::parse("http://localhost:1921/nginx-json/release%20notes%20%231.txt")
+                        .unwrap()
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+// Nginx autoindex with `autoindex_format xml;`, mtimes in RFC 3339
+
+use crate::listing::{FileSize, FileType, ListItem};
+
+use super::*;
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use tracing::debug;
+
+#[derive(Debug, Clone, Default)]
+pub struct NginxXmlListingParser;
+
+impl Parser for NginxXmlListingParser {
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let document = roxmltree::Document::parse(body)
+            .map_err(|e| ParseError::layout(url, &e.to_string(), body))?;
+        let mut items = Vec::new();
+        for node in document
+            .root_element()
+            .children()
+            .filter(|n| n.is_element())
+        {
+            let name = node.text().unwrap_or_default().to_string();
+            let type_ = match node.tag_name().name() {
+                "directory" => FileType::Directory,
+                "file" => FileType::File,
+                other => {
+                    debug!("Skipping {} with type {}", name, other);
+                    continue;
+                }
+            };
+            let href = join_name(url, &name, type_);
+            let mtime = node
+                .attribute("mtime")
+                .ok_or_else(|| ParseError::layout(url, "cannot find mtime", &name))?;
+            let mtime = DateTime::parse_from_rfc3339(mtime)
+                .map_err(|_| ParseError::date(url, mtime))?
+                .naive_utc();
+            let size = match type_ {
+                FileType::Directory => None,
+                FileType::File => match node.attribute("size") {
+                    Some(size) => Some(FileSize::Precise(
+                        size.parse().map_err(|_| ParseError::size(url, size))?,
+                    )),
+                    None => None,
+                },
+            };
+            items.push(
+                ListItem::new(href, name, type_, size, mtime)
+                    .with_timezone(FixedOffset::east_opt(0).unwrap()),
+            );
+        }
+        Ok(ListResult::List(items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    #[test]
+    fn test_nginx_xml() {
+        let items = parse_fixture(&NginxXmlListingParser, "http://localhost:1921/nginx-xml/");
+        match items {
+            ListResult::List(items) => {
+                assert_eq!(items.len(), 5);
+                assert_eq!(items[1].name, "snapshot");
+                assert_eq!(items[1].type_, FileType::Directory);
+                assert_eq!(items[1].size, None);
+                assert_eq!(
+                    items[1].url,
+                    Url::parse("http://localhost:1921/nginx-xml/snapshot/").unwrap()
+                );
+                assert_eq!(items[3].name, "monitoring-plugins-2.0.tar.gz");
+                assert_eq!(items[3].type_, FileType::File);
+                assert_eq!(items[3].size, Some(FileSize::Precise(2610000)));
+                assert_eq!(
+                    items[3].mtime,
+                    NaiveDateTime::parse_from_str("2014-07-11 23:17:05", "%Y-%m-%d %H:%M:%S")
+                        .unwrap()
+                );
+                assert_eq!(items[3].timezone, FixedOffset::east_opt(0));
+                assert_eq!(items[4].name, "release notes & #1.txt");
+                assert_eq!(
+                    items[4].url,
+                    Url::parse("http://localhost:1921/nginx-xml/release%20notes%20&%20%231.txt")
+                        .unwrap()
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+// PyPI simple index (PEP 503 HTML, PEP 691 JSON).
+// Root page lists projects as directories, and project pages list files (usually hosted elsewhere)
+// with hashes in URL fragments. A local index is written for pip, as upstream pages do not point to the mirror.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    checksum::Checksum,
+    listing::{FileSize, FileType, ListItem},
+};
+
+use super::*;
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use scraper::{Html, Selector};
+use serde::Deserialize;
+
+const ACCEPT: &str = "application/vnd.pypi.simple.v1+json, application/vnd.pypi.simple.v1+html;q=0.2, text/html;q=0.01";
+/// Characters to escape in href of local index
+const HREF: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?');
+/// Anchor attributes kept in local index
+const KEPT_ATTRIBUTES: &[&str] = &["data-requires-python", "data-yanked"];
+
+#[derive(Debug, Deserialize)]
+struct JsonProject {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum JsonYanked {
+    Flag(bool),
+    Reason(String),
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonFile {
+    filename: String,
+    url: String,
+    #[serde(default)]
+    hashes: BTreeMap<String, String>,
+    #[serde(rename = "requires-python")]
+    requires_python: Option<String>,
+    yanked: Option<JsonYanked>,
+    /// Since API version 1.1
+    size: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonPage {
+    #[serde(default)]
+    projects: Vec<JsonProject>,
+    #[serde(default)]
+    files: Vec<JsonFile>,
+}
+
+/// Normalized project name (PEP 503)
+fn normalize(name: &str) -> String {
+    let mut res = String::new();
+    for c in name.chars() {
+        if matches!(c, '-' | '_' | '.') {
+            if !res.ends_with('-') {
+                res.push('-');
+            }
+        } else {
+            res.extend(c.to_lowercase());
+        }
+    }
+    res
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn project_item(url: &Url, name: &str) -> ListItem {
+    ListItem::new(
+        url.clone(),
+        name.to_string(),
+        FileType::Directory,
+        None,
+        NaiveDateTime::default(),
+    )
+}
+
+/// File with known checksum, which is downloaded only if missing or broken.
+fn file_item(
+    url: &Url,
+    name: &str,
+    checksum: Option<Checksum>,
+    size: Option<u64>,
+    attributes: Vec<(String, String)>,
+) -> ListItem {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    let mut item = ListItem::new(
+        url,
+        name.to_string(),
+        FileType::File,
+        size.map(FileSize::Precise),
+        NaiveDateTime::default(),
+    );
+    item.checksum = checksum;
+    item.skip_check = true;
+    item.attributes = attributes;
+    item
+}
+
+/// Checksum in fragment like "#sha256=..."
+fn checksum_from_fragment(url: &Url) -> Option<Checksum> {
+    let (algorithm, digest) = url.fragment()?.split_once('=')?;
+    Checksum::new(algorithm, digest)
+}
+
+fn parse_html(body: &str, url: &Url) -> Result<Vec<ListItem>> {
+    let document = Html::parse_document(body);
+    let selector = Selector::parse("a[href]").unwrap();
+    let mut items = Vec::new();
+    for a in document.select(&selector) {
+        let href = a.value().attr("href").unwrap();
+        let href = url
+            .join(href)
+            .map_err(|_| ParseError::layout(url, "invalid href", &a.html()))?;
+        let name = href
+            .path_segments()
+            .and_then(|mut s| s.rfind(|s| !s.is_empty()))
+            .map(|s| percent_encoding::percent_decode_str(s).decode_utf8_lossy())
+            .ok_or_else(|| ParseError::layout(url, "cannot get name from href", &a.html()))?;
+        if href.path().ends_with('/') {
+            items.push(project_item(&href, &name));
+        } else {
+            let attributes = KEPT_ATTRIBUTES
+                .iter()
+                .filter_map(|k| Some((k.to_string(), a.value().attr(k)?.to_string())))
+                .collect();
+            items.push(file_item(
+                &href,
+                &name,
+                checksum_from_fragment(&href),
+                None,
+                attributes,
+            ));
+        }
+    }
+    Ok(items)
+}
+
+fn parse_json(body: &str, url: &Url) -> Result<Vec<ListItem>> {
+    let page: JsonPage =
+        serde_json::from_str(body).map_err(|e| ParseError::layout(url, &e.to_string(), body))?;
+    let mut items = Vec::new();
+    for project in page.projects {
+        let name = normalize(&project.name);
+        items.push(project_item(&url.join(&format!("{name}/"))?, &name));
+    }
+    for file in page.files {
+        let href = url.join(&file.url)?;
+        let checksum = ["sha256", "sha512"]
+            .iter()
+            .find_map(|a| Checksum::new(a, file.hashes.get(*a)?));
+        let mut attributes = vec![];
+        if let Some(requires_python) = file.requires_python {
+            attributes.push(("data-requires-python".to_string(), requires_python));
+        }
+        match file.yanked {
+            Some(JsonYanked::Reason(reason)) => {
+                attributes.push(("data-yanked".to_string(), reason))
+            }
+            Some(JsonYanked::Flag(true)) => {
+                attributes.push(("data-yanked".to_string(), String::new()))
+            }
+            _ => {}
+        }
+        items.push(file_item(
+            &href,
+            &file.filename,
+            checksum,
+            file.size,
+            attributes,
+        ));
+    }
+    Ok(items)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PypiListingParser;
+
+impl Parser for PypiListingParser {
+    fn get_list(&self, client: &Client, url: &Url) -> Result<ListResult> {
+        let resp = client
+            .get(url.clone())
+            .header(reqwest::header::ACCEPT, ACCEPT)
+            .send()?
+            .error_for_status()?;
+        let url = resp.url().clone();
+        let body = resp.text()?;
+        self.parse_body(&body, &url)
+    }
+
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        check_trailing_slash(url)?;
+        let items = if body.trim_start().starts_with('{') {
+            parse_json(body, url)?
+        } else {
+            parse_html(body, url)?
+        };
+        Ok(ListResult::List(items))
+    }
+
+    fn local_index(&self, items: &[ListItem]) -> Option<String> {
+        let mut page = String::from(
+            "<!DOCTYPE html>\n<html>\n<head><meta name=\"pypi:repository-version\" content=\"1.0\"><title>Links</title></head>\n<body>\n",
+        );
+        for item in items {
+            let mut href = utf8_percent_encode(&item.name, HREF).to_string();
+            match item.type_ {
+                FileType::Directory => href.push('/'),
+                FileType::File => {
+                    if let Some(checksum) = &item.checksum {
+                        href.push('#');
+                        href.push_str(&checksum.to_string().replacen(':', "=", 1));
+                    }
+                }
+            }
+            page.push_str(&format!("<a href=\"{}\"", escape(&href)));
+            for (k, v) in &item.attributes {
+                page.push_str(&format!(" {}=\"{}\"", k, escape(v)));
+            }
+            page.push_str(&format!(">{}</a><br/>\n", escape(&item.name)));
+        }
+        page.push_str("</body>\n</html>\n");
+        Some(page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pypi_root() {
+        match parse_fixture(&PypiListingParser, "http://localhost/pypi/simple/") {
+            ListResult::List(items) => {
+                let names: Vec<&str> = items.iter().map(|i| i.name.as_str()).collect();
+                assert_eq!(names, vec!["charset-normalizer", "requests"]);
+                assert_eq!(items[1].type_, FileType::Directory);
+                assert_eq!(
+                    items[1].url.as_str(),
+                    "http://localhost/pypi/simple/requests/"
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_pypi_project() {
+        let items =
+            match parse_fixture(&PypiListingParser, "http://localhost/pypi/simple/requests/") {
+                ListResult::List(items) => items,
+                _ => unreachable!(),
+            };
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].name, "requests-2.31.0-py3-none-any.whl");
+        assert_eq!(items[0].type_, FileType::File);
+        assert_eq!(
+            items[0].url.as_str(),
+            "http://localhost/pypi/packages/70/8e/0e2d847013cb52cd35b38c009bb167a1a26b2ce6cd6965bf26b47bc0bf44/requests-2.31.0-py3-none-any.whl"
+        );
+        assert_eq!(
+            items[0].checksum,
+            Checksum::new(
+                "sha256",
+                "58cd2187c01e70e6e26505bca751777aa9f2ee0b7f4300988b709f44e013003f"
+            )
+        );
+        assert!(items[0].skip_check);
+        assert_eq!(
+            items[0].attributes,
+            vec![("data-requires-python".to_string(), ">=3.7".to_string())]
+        );
+        assert_eq!(
+            items[1].attributes,
+            vec![
+                ("data-requires-python".to_string(), ">=3.7".to_string()),
+                ("data-yanked".to_string(), "broken".to_string())
+            ]
+        );
+
+        let page = PypiListingParser.local_index(&items).unwrap();
+        assert!(page.contains(
+            "<a href=\"requests-2.31.0-py3-none-any.whl#sha256=58cd2187c01e70e6e26505bca751777aa9f2ee0b7f4300988b709f44e013003f\" data-requires-python=\"&gt;=3.7\">requests-2.31.0-py3-none-any.whl</a>"
+        ));
+        assert!(page.contains("data-yanked=\"broken\""));
+    }
+
+    #[test]
+    fn test_pypi_json() {
+        let items = match parse_fixture(
+            &PypiListingParser,
+            "http://localhost/pypi-json/simple/requests/",
+        ) {
+            ListResult::List(items) => items,
+            _ => unreachable!(),
+        };
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].name, "requests-2.31.0.tar.gz");
+        assert_eq!(items[1].size, Some(FileSize::Precise(110794)));
+        assert_eq!(
+            items[1].url.as_str(),
+            "https://files.pythonhosted.org/packages/9d/be/10918a2eac4ae9f02f6cfe6414b7a155ccd8f7f9d4380d62fd5b955065c3/requests-2.31.0.tar.gz"
+        );
+        assert!(items[1].checksum.is_some());
+        assert_eq!(
+            items[1].attributes,
+            vec![
+                ("data-requires-python".to_string(), ">=3.7".to_string()),
+                ("data-yanked".to_string(), String::new())
+            ]
+        );
+
+        let items = match PypiListingParser
+            .parse_body(
+                r#"{"meta": {"api-version": "1.0"}, "projects": [{"name": "Charset_Normalizer"}]}"#,
+                &Url::parse("http://localhost/simple/").unwrap(),
+            )
+            .unwrap()
+        {
+            ListResult::List(items) => items,
+            _ => unreachable!(),
+        };
+        assert_eq!(items[0].name, "charset-normalizer");
+        assert_eq!(
+            items[0].url.as_str(),
+            "http://localhost/simple/charset-normalizer/"
+        );
+        assert_eq!(
+            PypiListingParser
+                .local_index(&items)
+                .unwrap()
+                .lines()
+                .nth(4),
+            Some("<a href=\"charset-normalizer/\">charset-normalizer</a><br/>")
+        );
+    }
+}
@@ -0,0 +1,306 @@
+// S3 compatible bucket listing (ListObjectsV2), for public buckets of AWS S3, R2, MinIO, etc.
+// The upstream URL should be the bucket root (path-style or virtual-hosted-style, ending with "/"),
+// optionally with "?prefix=some/dir/" to sync only a part of the bucket.
+// Directories are represented as the bucket root with "prefix" query,
+// thus they can be listed by this parser again.
+
+use crate::listing::{FileSize, FileType, ListItem};
+
+use std::collections::HashSet;
+
+use super::*;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, FixedOffset};
+use tracing::warn;
+
+#[derive(Debug, Clone, Default)]
+pub struct S3ListingParser;
+
+/// Split URL into bucket root URL (without query) and prefix.
+fn split_prefix(url: &Url) -> (Url, String) {
+    let mut prefix = url
+        .query_pairs()
+        .find(|(k, _)| k == "prefix")
+        .map(|(_, v)| v.to_string())
+        .unwrap_or_default();
+    if !prefix.is_empty() && !prefix.ends_with('/') {
+        prefix.push('/');
+    }
+    let mut base = url.clone();
+    base.set_query(None);
+    base.set_fragment(None);
+    (base, prefix)
+}
+
+fn child_text<'a>(node: roxmltree::Node<'a, 'a>, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|n| n.tag_name().name() == name)
+        .and_then(|n| n.text())
+}
+
+impl S3ListingParser {
+    /// Parse one page of ListObjectsV2 result. Returns items and continuation token of next page.
+    fn parse_page(
+        &self,
+        body: &str,
+        base: &Url,
+        prefix: &str,
+    ) -> Result<(Vec<ListItem>, Option<String>)> {
+        let document = roxmltree::Document::parse(body)
+            .map_err(|e| ParseError::layout(base, &e.to_string(), body))?;
+        let root = document.root_element();
+        if root.tag_name().name() != "ListBucketResult" {
+            return Err(
+                ParseError::layout(base, "root element is not ListBucketResult", body).into(),
+            );
+        }
+        let mut items = Vec::new();
+        let mut entries = 0;
+        for node in root.children().filter(|n| n.is_element()) {
+            match node.tag_name().name() {
+                "CommonPrefixes" => {
+                    entries += 1;
+                    let dir_prefix = child_text(node, "Prefix").ok_or_else(|| {
+                        ParseError::layout(base, "cannot find Prefix in CommonPrefixes", body)
+                    })?;
+                    let name = dir_prefix
+                        .strip_prefix(prefix)
+                        .unwrap_or(dir_prefix)
+                        .trim_end_matches('/');
+                    if name.is_empty() {
+                        continue;
+                    }
+                    let mut href = base.clone();
+                    href.query_pairs_mut().append_pair("prefix", dir_prefix);
+                    items.push(ListItem::new(
+                        href,
+                        name.to_string(),
+                        FileType::Directory,
+                        None,
+                        Default::default(),
+                    ));
+                }
+                "Contents" => {
+                    entries += 1;
+                    let key = child_text(node, "Key")
+                        .ok_or_else(|| ParseError::layout(base, "cannot find Key", body))?;
+                    let name = key.strip_prefix(prefix).unwrap_or(key);
+                    // Skip "directory placeholder" objects
+                    if name.is_empty() || name.ends_with('/') {
+                        continue;
+                    }
+                    let mut href = base.clone();
+                    href.path_segments_mut()
+                        .map_err(|_| anyhow!("Bucket URL cannot be a base"))?
+                        .pop_if_empty()
+                        .extend(key.split('/'));
+                    let size = child_text(node, "Size")
+                        .ok_or_else(|| ParseError::layout(base, "cannot find Size", key))?;
+                    let size = size
+                        .parse::<u64>()
+                        .map_err(|_| ParseError::size(base, size))?;
+                    let mtime = child_text(node, "LastModified")
+                        .ok_or_else(|| ParseError::layout(base, "cannot find LastModified", key))?;
+                    let mtime = DateTime::parse_from_rfc3339(mtime)
+                        .map_err(|_| ParseError::date(base, mtime))?
+                        .naive_utc();
+                    let mut item = ListItem::new(
+                        href,
+                        name.to_string(),
+                        FileType::File,
+                        Some(FileSize::Precise(size)),
+                        mtime,
+                    )
+                    .with_timezone(FixedOffset::east_opt(0).unwrap());
+                    item.etag = child_text(node, "ETag").map(|s| s.to_string());
+                    items.push(item);
+                }
+                _ => {}
+            }
+        }
+        let truncated = child_text(root, "IsTruncated") == Some("true");
+        if truncated && entries == 0 {
+            // Otherwise a broken server could keep us fetching empty pages
+            return Err(ParseError::layout(base, "truncated without any entry", body).into());
+        }
+        let token = if truncated {
+            Some(
+                child_text(root, "NextContinuationToken")
+                    .ok_or_else(|| {
+                        ParseError::layout(base, "truncated without NextContinuationToken", body)
+                    })?
+                    .to_string(),
+            )
+        } else {
+            None
+        };
+        Ok((items, token))
+    }
+
+    /// Fetch and parse all pages, until one is not truncated.
+    fn list_pages<F>(&self, base: &Url, prefix: &str, mut fetch: F) -> Result<Vec<ListItem>>
+    where
+        F: FnMut(Url) -> Result<String>,
+    {
+        let mut items = Vec::new();
+        let mut token: Option<String> = None;
+        let mut seen_tokens = HashSet::new();
+        loop {
+            let mut list_url = base.clone();
+            {
+                let mut query = list_url.query_pairs_mut();
+                query
+                    .append_pair("list-type", "2")
+                    .append_pair("delimiter", "/")
+                    .append_pair("prefix", prefix);
+                if let Some(token) = &token {
+                    query.append_pair("continuation-token", token);
+                }
+            }
+            let body = fetch(list_url)?;
+            let (mut page, next) = self.parse_page(&body, base, prefix)?;
+            items.append(&mut page);
+            match next {
+                Some(next) if !seen_tokens.insert(next.clone()) => {
+                    return Err(
+                        ParseError::layout(base, "continuation token repeats", &next).into(),
+                    );
+                }
+                Some(next) => token = Some(next),
+                None => break,
+            }
+        }
+        Ok(items)
+    }
+}
+
+impl Parser for S3ListingParser {
+    fn get_list(&self, client: &Client, url: &Url) -> Result<ListResult> {
+        let (base, prefix) = split_prefix(url);
+        check_trailing_slash(&base)?;
+        let items =
+            self.list_pages(
+                &base,
+                &prefix,
+                |list_url| Ok(get(client, list_url)?.text()?),
+            )?;
+        Ok(ListResult::List(items))
+    }
+
+    /// Parse a single page of bucket listing. Following pages are not fetched.
+    fn parse_body(&self, body: &str, url: &Url) -> Result<ListResult> {
+        let (base, prefix) = split_prefix(url);
+        let (items, token) = self.parse_page(body, &base, &prefix)?;
+        if token.is_some() {
+            warn!(
+                "Listing of {} is truncated, only the first page is parsed",
+                url
+            );
+        }
+        Ok(ListResult::List(items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    #[test]
+    fn test_s3() {
+        let items = parse_fixture(&S3ListingParser, "http://localhost:1921/s3/");
+        match items {
+            ListResult::List(items) => {
+                assert_eq!(items.len(), 4);
+                assert_eq!(items[0].name, "README.md");
+                assert_eq!(items[0].type_, FileType::File);
+                assert_eq!(items[0].size, Some(FileSize::Precise(1024)));
+                assert_eq!(
+                    items[0].mtime,
+                    NaiveDateTime::parse_from_str("2024-03-10 04:45:24", "%Y-%m-%d %H:%M:%S")
+                        .unwrap()
+                );
+                assert_eq!(items[0].timezone, FixedOffset::east_opt(0));
+                assert_eq!(
+                    items[0].etag.as_deref(),
+                    Some("\"3858f62230ac3c915f300c664312c63f\"")
+                );
+                assert_eq!(
+                    items[1].url,
+                    Url::parse("http://localhost:1921/s3/tool%20v1.0+build.tar.gz").unwrap()
+                );
+                assert_eq!(items[2].name, "debian");
+                assert_eq!(items[2].type_, FileType::Directory);
+                assert_eq!(
+                    items[2].url,
+                    Url::parse("http://localhost:1921/s3/?prefix=debian%2F").unwrap()
+                );
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_s3_page() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+  <Prefix>debian/dists/</Prefix>
+  <IsTruncated>true</IsTruncated>
+  <NextContinuationToken>1ueGcxLPRx1Tr/XYExHnhbYLgveDs2J/wm36Hy4vbOwM=</NextContinuationToken>
+  <Contents><Key>debian/dists/</Key><LastModified>2024-03-10T04:45:24.000Z</LastModified><Size>0</Size></Contents>
+  <Contents><Key>debian/dists/README</Key><LastModified>2024-03-10T04:45:24.000Z</LastModified><Size>12</Size></Contents>
+  <CommonPrefixes><Prefix>debian/dists/bookworm/</Prefix></CommonPrefixes>
+</ListBucketResult>"#;
+        let base = Url::parse("http://localhost:9000/bucket/").unwrap();
+        let (items, token) = S3ListingParser
+            .parse_page(body, &base, "debian/dists/")
+            .unwrap();
+        assert_eq!(
+            token.as_deref(),
+            Some("1ueGcxLPRx1Tr/XYExHnhbYLgveDs2J/wm36Hy4vbOwM=")
+        );
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].name, "README");
+        assert_eq!(
+            items[0].url,
+            Url::parse("http://localhost:9000/bucket/debian/dists/README").unwrap()
+        );
+        assert_eq!(items[1].name, "bookworm");
+        assert_eq!(
+            split_prefix(&items[1].url),
+            (base, "debian/dists/bookworm/".to_string())
+        );
+    }
+
+    #[test]
+    fn test_s3_pagination() {
+        let page = |token: &str, key: &str| {
+            format!(
+                r#"<ListBucketResult><IsTruncated>true</IsTruncated><NextContinuationToken>{token}</NextContinuationToken><Contents><Key>{key}</Key><LastModified>2024-03-10T04:45:24.000Z</LastModified><Size>1</Size></Contents></ListBucketResult>"#
+            )
+        };
+        let last = r#"<ListBucketResult><IsTruncated>false</IsTruncated><Contents><Key>c</Key><LastModified>2024-03-10T04:45:24.000Z</LastModified><Size>1</Size></Contents></ListBucketResult>"#;
+        let base = Url::parse("http://localhost:9000/bucket/").unwrap();
+
+        let mut pages = vec![last.to_string(), page("t2", "b"), page("t1", "a")];
+        let items = S3ListingParser
+            .list_pages(&base, "", |_| Ok(pages.pop().unwrap()))
+            .unwrap();
+        let names: Vec<&str> = items.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b", "c"]);
+
+        // Server returning the same token again and again
+        let mut fetched = 0;
+        assert!(S3ListingParser
+            .list_pages(&base, "", |_| {
+                fetched += 1;
+                Ok(page("t1", "a"))
+            })
+            .is_err());
+        assert_eq!(fetched, 2);
+
+        let empty = r#"<ListBucketResult><IsTruncated>true</IsTruncated><NextContinuationToken>t1</NextContinuationToken></ListBucketResult>"#;
+        assert!(S3ListingParser.parse_page(empty, &base, "").is_err());
+    }
+}
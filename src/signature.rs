@@ -0,0 +1,117 @@
+// Module for OpenPGP signatures of repository metadata (InRelease, Release.gpg, repomd.xml.asc)
+
+use std::{fs::File, path::Path};
+
+use anyhow::{anyhow, Result};
+use pgp::composed::{CleartextSignedMessage, Deserializable, DetachedSignature, SignedPublicKey};
+use pgp::{packet::Signature, types::KeyDetails};
+use tracing::{debug, info};
+
+/// Public keys trusted to sign metadata.
+pub struct Keyring {
+    keys: Vec<SignedPublicKey>,
+}
+
+impl Keyring {
+    /// Load keys from files, each of which might contain multiple keys, armored or binary.
+    pub fn load<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        let mut keys = vec![];
+        for path in paths {
+            let path = path.as_ref();
+            let (parsed, _) = SignedPublicKey::from_reader_many(File::open(path)?)?;
+            for key in parsed {
+                let key = key?;
+                key.verify_bindings()?;
+                debug!("Loaded key {:?} from {:?}", key.fingerprint(), path);
+                keys.push(key);
+            }
+        }
+        if keys.is_empty() {
+            return Err(anyhow!(
+                "No keys found in {:?}",
+                paths.iter().map(|p| p.as_ref()).collect::<Vec<_>>()
+            ));
+        }
+        info!("Loaded {} keys", keys.len());
+        Ok(Self { keys })
+    }
+
+    /// Whether signature is made by primary key or any subkey in keyring.
+    fn is_valid(&self, signature: &Signature, data: &[u8]) -> bool {
+        self.keys.iter().any(|key| {
+            signature.verify(key, data).is_ok()
+                || key
+                    .public_subkeys
+                    .iter()
+                    .any(|subkey| signature.verify(subkey, data).is_ok())
+        })
+    }
+
+    /// Verify detached signatures (like Release.gpg). At least one of them should be valid.
+    pub fn verify_detached(&self, data: &[u8], signatures: &[u8]) -> Result<()> {
+        let (signatures, _) = DetachedSignature::from_reader_many(signatures)?;
+        for signature in signatures {
+            if self.is_valid(&signature?.signature, data) {
+                return Ok(());
+            }
+        }
+        Err(anyhow!("No valid signature from keyring"))
+    }
+
+    /// Verify cleartext signed message (like InRelease). Returns the signed text with LF line endings.
+    pub fn verify_cleartext(&self, message: &str) -> Result<String> {
+        let (message, _) = CleartextSignedMessage::from_string(message)?;
+        let text = message.signed_text();
+        if message
+            .signatures()
+            .iter()
+            .any(|s| self.is_valid(s, text.as_bytes()))
+        {
+            Ok(text.replace("\r\n", "\n"))
+        } else {
+            Err(anyhow!("No valid signature from keyring"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/pgp")
+            .join(name)
+    }
+
+    #[test]
+    fn test_verify() {
+        let release = std::fs::read(fixture("Release")).unwrap();
+        let release_gpg = std::fs::read(fixture("Release.gpg")).unwrap();
+        let in_release = std::fs::read_to_string(fixture("InRelease")).unwrap();
+        let repomd = std::fs::read(fixture("repomd.xml")).unwrap();
+        let repomd_asc = std::fs::read(fixture("repomd.xml.asc")).unwrap();
+
+        for key in ["key.asc", "key.gpg"] {
+            let keyring = Keyring::load(&[fixture(key)]).unwrap();
+            keyring.verify_detached(&release, &release_gpg).unwrap();
+            // InRelease is signed by subkey
+            let text = keyring.verify_cleartext(&in_release).unwrap();
+            assert_eq!(text.trim_end().as_bytes(), release.trim_ascii_end());
+            keyring.verify_detached(&repomd, &repomd_asc).unwrap();
+
+            let mut tampered = release.clone();
+            tampered[0] = b'X';
+            assert!(keyring.verify_detached(&tampered, &release_gpg).is_err());
+            let tampered = in_release.replace("Suite: stable", "Suite: stale");
+            assert!(keyring.verify_cleartext(&tampered).is_err());
+        }
+
+        let other = Keyring::load(&[fixture("other.asc")]).unwrap();
+        assert!(other.verify_detached(&release, &release_gpg).is_err());
+        assert!(other.verify_cleartext(&in_release).is_err());
+        assert!(Keyring::load(&[fixture("Release")]).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+// Module for metadata-last sync: repository metadata is downloaded into a staging directory,
+// and published after all other files are ready, so that clients never see metadata
+// referring to packages which are not downloaded yet.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Context, Result};
+use tracing::{debug, info, warn};
+
+use crate::{regex_process::ExpandedRegex, signature::Keyring};
+
+pub const STAGING_DIR: &str = ".tsumugu-staging";
+
+/// Top-level index files, which are published after all other metadata.
+const INDEX_FILES: &[&str] = &[
+    "InRelease",
+    "Release",
+    "Release.gpg",
+    "repomd.xml",
+    "repomd.xml.asc",
+    "repomd.xml.key",
+];
+
+pub struct Staging {
+    download_dir: PathBuf,
+    regexes: Vec<ExpandedRegex>,
+    /// Relative paths of staged files
+    files: Mutex<Vec<String>>,
+}
+
+impl Staging {
+    pub fn new(download_dir: &Path, regexes: &[ExpandedRegex]) -> Self {
+        Self {
+            download_dir: download_dir.to_path_buf(),
+            regexes: regexes.to_vec(),
+            files: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        self.download_dir.join(STAGING_DIR)
+    }
+
+    pub fn is_metadata(&self, relative: &str) -> bool {
+        self.regexes.iter().any(|r| r.is_match(relative))
+    }
+
+    /// Where to download the metadata file.
+    pub fn path(&self, relative: &str) -> PathBuf {
+        self.dir().join(relative)
+    }
+
+    /// Record a downloaded file to publish later.
+    pub fn stage(&self, relative: &str) {
+        self.files.lock().unwrap().push(relative.to_string());
+    }
+
+    /// Staged file if it is downloaded in this run, otherwise the published one.
+    fn current(&self, staged: &HashSet<String>, relative: &str) -> PathBuf {
+        if staged.contains(relative) {
+            self.path(relative)
+        } else {
+            self.download_dir.join(relative)
+        }
+    }
+
+    /// Verify signatures of staged metadata, and of those signed by staged signatures.
+    /// Every Release and repomd.xml should have a valid detached signature, either staged or published before.
+    /// Release without Release.gpg is also accepted if it is the same as signed text of a valid InRelease next to it.
+    pub fn verify(&self, keyring: &Keyring) -> Result<()> {
+        let staged: HashSet<String> = self.files.lock().unwrap().iter().cloned().collect();
+        let mut checked = HashSet::new();
+        for relative in &staged {
+            let (dir, name) = match relative.rsplit_once('/') {
+                Some((dir, name)) => (format!("{dir}/"), name),
+                None => (String::new(), relative.as_str()),
+            };
+            let (data, signature, in_release) = match name {
+                "InRelease" => {
+                    let message = std::fs::read_to_string(self.path(relative))?;
+                    keyring
+                        .verify_cleartext(&message)
+                        .with_context(|| format!("Bad signature of {relative}"))?;
+                    info!("Verified {}", relative);
+                    continue;
+                }
+                "Release" | "Release.gpg" => (
+                    dir.clone() + "Release",
+                    dir.clone() + "Release.gpg",
+                    Some(dir + "InRelease"),
+                ),
+                "repomd.xml" | "repomd.xml.asc" => {
+                    (dir.clone() + "repomd.xml", dir + "repomd.xml.asc", None)
+                }
+                _ => continue,
+            };
+            let data_path = self.current(&staged, &data);
+            // A staged signature alone has nothing to vouch for
+            if !checked.insert(data.clone()) || !data_path.exists() {
+                continue;
+            }
+            let content =
+                std::fs::read(data_path).with_context(|| format!("Failed to read {data}"))?;
+            let signature_path = self.current(&staged, &signature);
+            let in_release = in_release
+                .map(|r| self.current(&staged, &r))
+                .filter(|p| p.exists());
+            if signature_path.exists() {
+                keyring
+                    .verify_detached(&content, &std::fs::read(signature_path)?)
+                    .with_context(|| format!("Bad signature of {data}"))?;
+            } else if let Some(in_release) = in_release {
+                let text = keyring
+                    .verify_cleartext(&std::fs::read_to_string(&in_release)?)
+                    .with_context(|| format!("Bad signature of {:?}", in_release))?;
+                if text.trim_end().as_bytes() != content.trim_ascii_end() {
+                    return Err(anyhow!("{data} is not signed, and differs from InRelease"));
+                }
+            } else {
+                return Err(anyhow!("{data} is not signed"));
+            }
+            info!("Verified {}", data);
+        }
+        Ok(())
+    }
+
+    /// Move staged files to their place, with top-level index files at last.
+    pub fn publish(&self) -> Result<()> {
+        let mut files = self.files.lock().unwrap().clone();
+        files.sort_by_key(|f| is_index_file(f));
+        info!("Publishing {} metadata files", files.len());
+        for relative in files {
+            let target = self.download_dir.join(&relative);
+            debug!("Publishing {:?}", target);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::rename(self.path(&relative), target)?;
+        }
+        self.discard();
+        Ok(())
+    }
+
+    /// Remove staging directory and everything inside.
+    pub fn discard(&self) {
+        let dir = self.dir();
+        if dir.exists() {
+            if let Err(e) = std::fs::remove_dir_all(&dir) {
+                warn!("Failed to remove {:?}: {:?}", dir, e);
+            }
+        }
+    }
+}
+
+fn is_index_file(relative: &str) -> bool {
+    let name = relative.rsplit('/').next().unwrap_or(relative);
+    INDEX_FILES.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_order() {
+        let staging = Staging::new(
+            Path::new("/srv/mirror"),
+            &[
+                "(^|/)dists/".parse().unwrap(),
+                "(^|/)repodata/".parse().unwrap(),
+            ],
+        );
+        assert!(staging.is_metadata("debian/dists/bookworm/InRelease"));
+        assert!(staging.is_metadata("dists/bookworm/main/binary-amd64/Packages.gz"));
+        assert!(staging.is_metadata("rocky/9/BaseOS/x86_64/os/repodata/repomd.xml"));
+        assert!(!staging.is_metadata("debian/pool/main/a/acl/acl_2.3.1-3_amd64.deb"));
+        assert_eq!(
+            staging.path("dists/bookworm/Release"),
+            Path::new("/srv/mirror/.tsumugu-staging/dists/bookworm/Release")
+        );
+
+        let mut files = vec![
+            "dists/bookworm/InRelease",
+            "dists/bookworm/main/binary-amd64/Packages.gz",
+            "repodata/repomd.xml",
+            "repodata/abc-primary.xml.gz",
+        ];
+        files.sort_by_key(|f| is_index_file(f));
+        assert_eq!(
+            files,
+            vec![
+                "dists/bookworm/main/binary-amd64/Packages.gz",
+                "repodata/abc-primary.xml.gz",
+                "dists/bookworm/InRelease",
+                "repodata/repomd.xml",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/pgp");
+        let keyring = Keyring::load(&[fixtures.join("key.asc")]).unwrap();
+        let download_dir =
+            std::env::temp_dir().join(format!("tsumugu-staging-{}", std::process::id()));
+        let staging = Staging::new(&download_dir, &["(^|/)dists/".parse().unwrap()]);
+        // Release.gpg is published before, and only Release is updated
+        std::fs::create_dir_all(download_dir.join("dists/stable")).unwrap();
+        std::fs::copy(
+            fixtures.join("Release.gpg"),
+            download_dir.join("dists/stable/Release.gpg"),
+        )
+        .unwrap();
+        std::fs::create_dir_all(staging.path("dists/stable")).unwrap();
+        std::fs::copy(
+            fixtures.join("Release"),
+            staging.path("dists/stable/Release"),
+        )
+        .unwrap();
+        staging.stage("dists/stable/Release");
+        staging.verify(&keyring).unwrap();
+
+        std::fs::write(staging.path("dists/stable/Release"), "Suite: evil\n").unwrap();
+        assert!(staging.verify(&keyring).is_err());
+
+        // Without Release.gpg, Release must match InRelease
+        std::fs::remove_file(download_dir.join("dists/stable/Release.gpg")).unwrap();
+        assert!(staging.verify(&keyring).is_err());
+        std::fs::copy(
+            fixtures.join("InRelease"),
+            staging.path("dists/stable/InRelease"),
+        )
+        .unwrap();
+        staging.stage("dists/stable/InRelease");
+        assert!(staging.verify(&keyring).is_err());
+        std::fs::copy(
+            fixtures.join("Release"),
+            staging.path("dists/stable/Release"),
+        )
+        .unwrap();
+        staging.verify(&keyring).unwrap();
+
+        // Unsigned repomd.xml
+        std::fs::create_dir_all(staging.path("repodata")).unwrap();
+        std::fs::copy(
+            fixtures.join("repomd.xml"),
+            staging.path("repodata/repomd.xml"),
+        )
+        .unwrap();
+        staging.stage("repodata/repomd.xml");
+        assert!(staging.verify(&keyring).is_err());
+        std::fs::remove_dir_all(&download_dir).unwrap();
+    }
+}
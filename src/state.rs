@@ -0,0 +1,238 @@
+// Module for the sync state, persisted between runs.
+// For each synced file, it records how the remote looked like when the local file was last verified,
+// so that files unchanged since then need no further check (e.g. HEAD).
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::listing::ListItem;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateEntry {
+    pub url: String,
+    /// Size in listing (estimated if humanized)
+    pub size: Option<u64>,
+    /// mtime in listing, as a timestamp of its naive datetime
+    pub mtime: i64,
+    pub etag: Option<String>,
+    /// Checksum from repository metadata, as extensions give no mtime or ETag
+    #[serde(default)]
+    pub checksum: Option<String>,
+    /// Size of local file when it is verified
+    pub local_size: u64,
+    /// mtime (unix timestamp) of local file when it is verified
+    pub local_mtime: i64,
+    /// When the local file is verified (downloaded or confirmed up-to-date)
+    pub verified: i64,
+}
+
+fn local_stat(path: &Path) -> Option<(u64, i64)> {
+    let metadata = path.metadata().ok()?;
+    let mtime = filetime::FileTime::from_last_modification_time(&metadata);
+    Some((metadata.len(), mtime.unix_seconds()))
+}
+
+impl StateEntry {
+    /// Build entry from remote item and current local file. None if local file does not exist.
+    pub fn new(item: &ListItem, path: &Path) -> Option<Self> {
+        let (local_size, local_mtime) = local_stat(path)?;
+        Some(Self {
+            url: item.url.to_string(),
+            size: item.size.map(|s| s.get_estimated()),
+            mtime: item.mtime.and_utc().timestamp(),
+            etag: item.etag.clone(),
+            checksum: item.checksum.as_ref().map(|c| c.to_string()),
+            local_size,
+            local_mtime,
+            verified: chrono::Utc::now().timestamp(),
+        })
+    }
+
+    /// Whether remote item is the same as recorded.
+    pub fn matches_remote(&self, item: &ListItem) -> bool {
+        self.url == item.url.as_str()
+            && self.size == item.size.map(|s| s.get_estimated())
+            && self.mtime == item.mtime.and_utc().timestamp()
+            && self.etag == item.etag
+            && self.checksum == item.checksum.as_ref().map(|c| c.to_string())
+    }
+
+    /// Whether local file has not been touched since verified.
+    pub fn matches_local(&self, path: &Path) -> bool {
+        local_stat(path) == Some((self.local_size, self.local_mtime))
+    }
+
+    /// Same entry, ignoring verification time.
+    fn same_as(&self, other: &Self) -> bool {
+        Self {
+            verified: other.verified,
+            ..self.clone()
+        } == *other
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct StateDiff {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Sync state, keyed by path relative to download dir.
+pub struct SyncState {
+    path: PathBuf,
+    previous: BTreeMap<String, StateEntry>,
+    current: Mutex<BTreeMap<String, StateEntry>>,
+}
+
+impl SyncState {
+    /// Load state from file. A missing file is taken as an empty state (first run).
+    pub fn load(path: &Path) -> Result<Self> {
+        let previous: BTreeMap<String, StateEntry> = match std::fs::read(path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!(
+                    "State file {:?} not found, starting with an empty state",
+                    path
+                );
+                BTreeMap::new()
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            current: Mutex::new(previous.clone()),
+            previous,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, relative: &str) -> Option<StateEntry> {
+        self.current.lock().unwrap().get(relative).cloned()
+    }
+
+    pub fn record(&self, relative: &str, entry: StateEntry) {
+        self.current
+            .lock()
+            .unwrap()
+            .insert(relative.to_string(), entry);
+    }
+
+    /// Drop entries of files which are not in remote anymore.
+    pub fn retain(&self, download_dir: &Path, remote_list: &HashSet<PathBuf>) {
+        self.current
+            .lock()
+            .unwrap()
+            .retain(|relative, _| remote_list.contains(&download_dir.join(relative)));
+    }
+
+    /// Changes since the state is loaded.
+    pub fn diff(&self) -> StateDiff {
+        let current = self.current.lock().unwrap();
+        let mut diff = StateDiff::default();
+        for (relative, entry) in current.iter() {
+            match self.previous.get(relative) {
+                None => diff.added.push(relative.clone()),
+                Some(old) if !old.same_as(entry) => diff.updated.push(relative.clone()),
+                _ => {}
+            }
+        }
+        for relative in self.previous.keys() {
+            if !current.contains_key(relative) {
+                diff.removed.push(relative.clone());
+            }
+        }
+        diff
+    }
+
+    /// Write state to file atomically.
+    pub fn save(&self) -> Result<()> {
+        let data = serde_json::to_vec(&*self.current.lock().unwrap())?;
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, data)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        debug!("State saved to {:?}", self.path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use url::Url;
+
+    use super::*;
+    use crate::listing::{FileSize, FileType};
+
+    fn item() -> ListItem {
+        ListItem::new(
+            Url::parse("http://localhost/s3/index.html").unwrap(),
+            "index.html".to_string(),
+            FileType::File,
+            Some(FileSize::Precise(1024)),
+            NaiveDateTime::parse_from_str("2024-03-10 04:45:24", "%Y-%m-%d %H:%M:%S").unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_entry() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/s3/index.html");
+        let entry = StateEntry::new(&item(), &path).unwrap();
+        assert!(entry.matches_remote(&item()));
+        assert!(entry.matches_local(&path));
+        let mut changed = item();
+        changed.etag = Some("\"abc\"".to_string());
+        assert!(!entry.matches_remote(&changed));
+        assert!(!entry.matches_local(&path.with_file_name("missing")));
+        assert!(StateEntry::new(&item(), &path.with_file_name("missing")).is_none());
+    }
+
+    #[test]
+    fn test_diff() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/s3/index.html");
+        let entry = StateEntry::new(&item(), &path).unwrap();
+        let state = SyncState {
+            path: PathBuf::new(),
+            previous: BTreeMap::from([
+                ("a".to_string(), entry.clone()),
+                ("b".to_string(), entry.clone()),
+                ("c".to_string(), entry.clone()),
+            ]),
+            current: Mutex::new(BTreeMap::new()),
+        };
+        state.record(
+            "a",
+            StateEntry {
+                verified: entry.verified + 60,
+                ..entry.clone()
+            },
+        );
+        state.record(
+            "b",
+            StateEntry {
+                local_size: 1,
+                ..entry.clone()
+            },
+        );
+        state.record("d", entry.clone());
+        assert_eq!(
+            state.diff(),
+            StateDiff {
+                added: vec!["d".to_string()],
+                updated: vec!["b".to_string()],
+                removed: vec!["c".to_string()],
+            }
+        );
+    }
+}
@@ -3,7 +3,6 @@ use anyhow::Result;
 use chrono::FixedOffset;
 use chrono::TimeZone;
 use chrono::{DateTime, Utc};
-use futures_util::Future;
 use tracing::warn;
 use url::Url;
 
@@ -57,27 +56,39 @@ pub fn again<T>(closure: impl Fn() -> Result<T>, retry: usize) -> Result<T> {
     }
 }
 
-pub async fn again_async<T, Fut, F: Fn() -> Fut>(f: F, retry: usize) -> Result<T>
-where
-    Fut: Future<Output = Result<T>>,
-{
-    let mut count = 0;
-    loop {
-        match f().await {
-            Ok(x) => return Ok(x),
-            Err(e) => {
-                warn!("Error: {:?}, retrying {}/{}", e, count, retry);
-                count += 1;
-                if count > retry {
-                    return Err(e);
-                }
-            }
-        }
+pub async fn get_async(client: &reqwest::Client, url: Url) -> Result<reqwest::Response> {
+    Ok(client.get(url).send().await?.error_for_status()?)
+}
+
+/// GET from offset, if the resource still matches validator (strong ETag or Last-Modified).
+/// Server would return 206 to resume, or 200 with full content when validator changed or Range is not supported.
+pub async fn get_async_range(
+    client: &reqwest::Client,
+    url: Url,
+    offset: u64,
+    validator: &str,
+) -> Result<reqwest::Response> {
+    let resp = client
+        .get(url)
+        .header(reqwest::header::RANGE, format!("bytes={offset}-"))
+        .header(reqwest::header::IF_RANGE, validator)
+        .send()
+        .await?;
+    if resp.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        return Ok(resp);
     }
+    Ok(resp.error_for_status()?)
 }
 
-pub async fn get_async(client: &reqwest::Client, url: Url) -> Result<reqwest::Response> {
-    Ok(client.get(url).send().await?.error_for_status()?)
+/// Get start offset from "Content-Range: bytes 100-999/1000".
+pub fn content_range_start(content_range: &str) -> Option<u64> {
+    content_range
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
 }
 
 #[allow(dead_code)]
@@ -121,4 +132,12 @@ mod tests {
         let utc = naive_to_utc(&naive, None);
         assert_eq!(utc.to_string(), "2021-01-01 00:00:00 UTC");
     }
+
+    #[test]
+    fn test_content_range_start() {
+        assert_eq!(content_range_start("bytes 100-999/1000"), Some(100));
+        assert_eq!(content_range_start("bytes 0-0/*"), Some(0));
+        assert_eq!(content_range_start("bytes */1000"), None);
+        assert_eq!(content_range_start("items 1-2/3"), None);
+    }
 }